@@ -0,0 +1,39 @@
+/*
+ * C API of the ub compiler, implemented in src/capi.rs. Build the library with
+ * `cargo build --release --features capi` and link against libub.so (libub.dylib, ub.dll).
+ *
+ * There is no interpreter yet, so programs can only be checked, not run.
+ */
+
+#ifndef UB_H
+#define UB_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct UbCompilation UbCompilation;
+
+/*
+ * Checks `len` bytes of UTF-8 source code. `source` doesn't need to be NUL terminated.
+ * Returns NULL if the source isn't UTF-8 or the compiler crashed.
+ */
+UbCompilation *ub_compile(const char *source, size_t len);
+
+/*
+ * The errors as a NUL terminated JSON array of `{ message, start, end }`, where the positions are
+ * `{ line, column, offset }`. Lines and columns are zero based, columns count UTF-16 code units
+ * and offsets count bytes. The string is freed together with the compilation.
+ */
+const char *ub_get_diagnostics(const UbCompilation *compilation);
+
+/* Frees a compilation. Does nothing if `compilation` is NULL. */
+void ub_free(UbCompilation *compilation);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* UB_H */
@@ -1,5 +1,10 @@
 use std::{ops::Range, path::PathBuf};
 
+pub mod build;
+pub mod diff;
+pub mod nodes;
+pub mod visit;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NodeId(u32);
 
@@ -19,6 +24,7 @@ pub struct Ty {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TyKind {
+    Bool,
     Ptr(Box<Ty>),
     Name(String),
 }
@@ -32,27 +38,34 @@ pub enum Item {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FnDecl {
     pub name: String,
+    pub name_span: Span,
     pub params: Vec<NameTyPair>,
     pub ret_ty: Option<Ty>,
     pub id: NodeId,
     pub span: Span,
     pub body: Vec<Stmt>,
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NameTyPair {
     pub name: String,
+    pub name_span: Span,
     pub ty: Ty,
     pub id: NodeId,
     pub span: Span,
+    /// Only struct fields can be documented.
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StructDecl {
     pub name: String,
+    pub name_span: Span,
     pub fields: Vec<NameTyPair>,
     pub id: NodeId,
     pub span: Span,
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -62,6 +75,9 @@ pub enum Stmt {
     IfStmt(IfStmt),
     WhileStmt(WhileStmt),
     LoopStmt(LoopStmt),
+    Return(ReturnStmt),
+    Break(Span),
+    Continue(Span),
     Item(Item),
     Expr(Expr),
 }
@@ -69,6 +85,7 @@ pub enum Stmt {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VarDecl {
     pub name: String,
+    pub name_span: Span,
     pub ty: Option<Ty>,
     pub rhs: Option<Expr>,
     pub span: Span,
@@ -108,6 +125,13 @@ pub struct LoopStmt {
     pub span: Span,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReturnStmt {
+    /// `None` for a bare `return;`.
+    pub expr: Option<Expr>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Expr {
     pub kind: ExprKind,
@@ -146,10 +170,12 @@ pub enum BinOpKind {
     Sub,
     Mul,
     Div,
-    Mod,
+    Rem,
     Shr,
     Shl,
+    /// `&&`. Short-circuits: the right side is only evaluated if the left side is `true`.
     And,
+    /// `||`. Short-circuits: the right side is only evaluated if the left side is `false`.
     Or,
     BitAnd,
     BitOr,
@@ -187,6 +213,7 @@ pub struct Call {
 pub enum Literal {
     String(String, Span),
     Integer(u64, Span),
+    Bool(bool, Span),
 }
 
 impl NodeId {
@@ -194,3 +221,35 @@ impl NodeId {
         Self(id)
     }
 }
+
+impl Item {
+    pub fn name(&self) -> &str {
+        match self {
+            Item::FnDecl(fn_decl) => &fn_decl.name,
+            Item::StructDecl(struct_decl) => &struct_decl.name,
+        }
+    }
+
+    pub fn span(&self) -> &Span {
+        match self {
+            Item::FnDecl(fn_decl) => &fn_decl.span,
+            Item::StructDecl(struct_decl) => &struct_decl.span,
+        }
+    }
+}
+
+impl Stmt {
+    pub fn span(&self) -> &Span {
+        match self {
+            Stmt::VarDecl(decl) => &decl.span,
+            Stmt::Assignment(assign) => &assign.span,
+            Stmt::IfStmt(if_stmt) => &if_stmt.span,
+            Stmt::WhileStmt(while_stmt) => &while_stmt.span,
+            Stmt::LoopStmt(loop_stmt) => &loop_stmt.span,
+            Stmt::Return(return_stmt) => &return_stmt.span,
+            Stmt::Break(span) | Stmt::Continue(span) => span,
+            Stmt::Item(item) => item.span(),
+            Stmt::Expr(expr) => &expr.span,
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Constructors for building trees directly, so that tests of later passes don't have to go
+//! through source text.
+//!
+//! Spans are made up: every node ends after everything that was built before it and covers its
+//! children, so spans nest and follow the order in which the nodes were built. Names get their
+//! own span, which comes after the children of their node.
+
+use std::cell::Cell;
+
+use super::{
+    Assignment, BinOp, BinOpKind, Call, ElsePart, Expr, ExprKind, FieldAccess, File, FnDecl,
+    IfStmt, Item, Literal, LoopStmt, NameTyPair, NodeId, ReturnStmt, Span, Stmt, StructDecl, Ty,
+    TyKind, UnaryOp, UnaryOpKind, VarDecl, WhileStmt,
+};
+
+/// Hands out node ids and spans. Two builders produce the same tree for the same calls.
+#[derive(Default)]
+pub struct Builder {
+    next_id: Cell<u32>,
+    next_offset: Cell<usize>,
+}
+
+impl Builder {
+    fn next_id(&self) -> NodeId {
+        let next = self.next_id.get();
+        self.next_id.set(next + 1);
+        NodeId::new(next)
+    }
+
+    /// A span of length one after all previous spans.
+    fn leaf(&self) -> Span {
+        let next = self.next_offset.get();
+        self.next_offset.set(next + 1);
+        next..next + 1
+    }
+
+    /// A new span that covers `children`.
+    fn around<'a>(&self, children: impl IntoIterator<Item = &'a Span>) -> Span {
+        let start = children.into_iter().map(|span| span.start).min();
+        let end = self.leaf().end;
+        start.unwrap_or(end - 1)..end
+    }
+
+    pub fn file(&self, name: &str, items: Vec<Item>) -> File {
+        File {
+            name: name.into(),
+            items,
+        }
+    }
+
+    pub fn ty_bool(&self) -> Ty {
+        Ty {
+            span: self.leaf(),
+            kind: TyKind::Bool,
+        }
+    }
+
+    pub fn ty_name(&self, name: &str) -> Ty {
+        Ty {
+            span: self.leaf(),
+            kind: TyKind::Name(name.to_owned()),
+        }
+    }
+
+    pub fn ty_ptr(&self, ty: Ty) -> Ty {
+        Ty {
+            span: self.around([&ty.span]),
+            kind: TyKind::Ptr(Box::new(ty)),
+        }
+    }
+
+    pub fn fn_(
+        &self,
+        name: &str,
+        params: Vec<NameTyPair>,
+        ret_ty: Option<Ty>,
+        body: Vec<Stmt>,
+    ) -> Item {
+        let name_span = self.leaf();
+        let children = params
+            .iter()
+            .map(|param| &param.span)
+            .chain(ret_ty.as_ref().map(|ty| &ty.span))
+            .chain(body.iter().map(Stmt::span))
+            .chain([&name_span]);
+        Item::FnDecl(FnDecl {
+            name: name.to_owned(),
+            span: self.around(children),
+            name_span,
+            params,
+            ret_ty,
+            id: self.next_id(),
+            body,
+            doc: None,
+        })
+    }
+
+    /// A function parameter or struct field.
+    pub fn param(&self, name: &str, ty: Ty) -> NameTyPair {
+        let name_span = self.leaf();
+        NameTyPair {
+            name: name.to_owned(),
+            span: self.around([&ty.span, &name_span]),
+            name_span,
+            ty,
+            id: self.next_id(),
+            doc: None,
+        }
+    }
+
+    pub fn struct_(&self, name: &str, fields: Vec<NameTyPair>) -> Item {
+        let name_span = self.leaf();
+        let children = fields.iter().map(|field| &field.span).chain([&name_span]);
+        Item::StructDecl(StructDecl {
+            name: name.to_owned(),
+            span: self.around(children),
+            name_span,
+            fields,
+            id: self.next_id(),
+            doc: None,
+        })
+    }
+
+    pub fn let_(&self, name: &str, ty: Option<Ty>, rhs: Option<Expr>) -> Stmt {
+        let name_span = self.leaf();
+        let children = ty
+            .as_ref()
+            .map(|ty| &ty.span)
+            .into_iter()
+            .chain(rhs.as_ref().map(|rhs| &rhs.span))
+            .chain([&name_span]);
+        Stmt::VarDecl(VarDecl {
+            name: name.to_owned(),
+            span: self.around(children),
+            name_span,
+            ty,
+            rhs,
+        })
+    }
+
+    pub fn assign(&self, place: Expr, rhs: Expr) -> Stmt {
+        Stmt::Assignment(Assignment {
+            span: self.around([&place.span, &rhs.span]),
+            place,
+            rhs,
+        })
+    }
+
+    pub fn if_(&self, cond: Expr, body: Vec<Stmt>, else_part: Option<ElsePart>) -> Stmt {
+        Stmt::IfStmt(self.if_stmt(cond, body, else_part))
+    }
+
+    pub fn else_(&self, body: Vec<Stmt>) -> ElsePart {
+        let span = self.around(body.iter().map(Stmt::span));
+        ElsePart::Else(body, span)
+    }
+
+    pub fn else_if(&self, cond: Expr, body: Vec<Stmt>, else_part: Option<ElsePart>) -> ElsePart {
+        ElsePart::ElseIf(Box::new(self.if_stmt(cond, body, else_part)))
+    }
+
+    fn if_stmt(&self, cond: Expr, body: Vec<Stmt>, else_part: Option<ElsePart>) -> IfStmt {
+        let else_span = match &else_part {
+            Some(ElsePart::Else(_, span)) => Some(span),
+            Some(ElsePart::ElseIf(if_stmt)) => Some(&if_stmt.span),
+            None => None,
+        };
+        let children = [&cond.span]
+            .into_iter()
+            .chain(body.iter().map(Stmt::span))
+            .chain(else_span);
+        IfStmt {
+            span: self.around(children),
+            cond,
+            body,
+            else_part,
+        }
+    }
+
+    pub fn while_(&self, cond: Expr, body: Vec<Stmt>) -> Stmt {
+        let children = [&cond.span].into_iter().chain(body.iter().map(Stmt::span));
+        Stmt::WhileStmt(WhileStmt {
+            span: self.around(children),
+            cond,
+            body,
+        })
+    }
+
+    pub fn loop_(&self, body: Vec<Stmt>) -> Stmt {
+        Stmt::LoopStmt(LoopStmt {
+            span: self.around(body.iter().map(Stmt::span)),
+            body,
+        })
+    }
+
+    pub fn return_(&self, expr: Option<Expr>) -> Stmt {
+        Stmt::Return(ReturnStmt {
+            span: self.around(expr.as_ref().map(|expr| &expr.span)),
+            expr,
+        })
+    }
+
+    pub fn break_(&self) -> Stmt {
+        Stmt::Break(self.leaf())
+    }
+
+    pub fn continue_(&self) -> Stmt {
+        Stmt::Continue(self.leaf())
+    }
+
+    pub fn item(&self, item: Item) -> Stmt {
+        Stmt::Item(item)
+    }
+
+    pub fn expr(&self, expr: Expr) -> Stmt {
+        Stmt::Expr(expr)
+    }
+
+    fn expr_kind(&self, kind: ExprKind, span: Span) -> Expr {
+        Expr {
+            kind,
+            id: self.next_id(),
+            span,
+        }
+    }
+
+    pub fn binop(&self, kind: BinOpKind, lhs: Expr, rhs: Expr) -> Expr {
+        let span = self.around([&lhs.span, &rhs.span]);
+        let bin_op = BinOp {
+            kind,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            span: span.clone(),
+        };
+        self.expr_kind(ExprKind::BinOp(bin_op), span)
+    }
+
+    pub fn unary(&self, kind: UnaryOpKind, expr: Expr) -> Expr {
+        let span = self.around([&expr.span]);
+        let unary_op = UnaryOp {
+            expr: Box::new(expr),
+            kind,
+            span: span.clone(),
+        };
+        self.expr_kind(ExprKind::UnaryOp(unary_op), span)
+    }
+
+    pub fn field_access(&self, expr: Expr, field_name: &str) -> Expr {
+        let span = self.around([&expr.span]);
+        let field_access = FieldAccess {
+            expr: Box::new(expr),
+            field_name: field_name.to_owned(),
+        };
+        self.expr_kind(ExprKind::FieldAccess(field_access), span)
+    }
+
+    pub fn call(&self, callee: Expr, args: Vec<Expr>) -> Expr {
+        let span = self.around(
+            [&callee.span]
+                .into_iter()
+                .chain(args.iter().map(|arg| &arg.span)),
+        );
+        let call = Call {
+            callee: Box::new(callee),
+            args,
+        };
+        self.expr_kind(ExprKind::Call(call), span)
+    }
+
+    pub fn int(&self, value: u64) -> Expr {
+        let span = self.leaf();
+        self.expr_kind(
+            ExprKind::Literal(Literal::Integer(value, span.clone())),
+            span,
+        )
+    }
+
+    pub fn string(&self, value: &str) -> Expr {
+        let span = self.leaf();
+        self.expr_kind(
+            ExprKind::Literal(Literal::String(value.to_owned(), span.clone())),
+            span,
+        )
+    }
+
+    pub fn bool_(&self, value: bool) -> Expr {
+        let span = self.leaf();
+        self.expr_kind(ExprKind::Literal(Literal::Bool(value, span.clone())), span)
+    }
+
+    pub fn name(&self, name: &str) -> Expr {
+        self.expr_kind(ExprKind::Name(name.to_owned()), self.leaf())
+    }
+
+    pub fn array(&self, elements: Vec<Expr>) -> Expr {
+        let span = self.around(elements.iter().map(|element| &element.span));
+        self.expr_kind(ExprKind::Array(elements), span)
+    }
+}
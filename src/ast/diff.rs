@@ -0,0 +1,399 @@
+//! Structural differences between two versions of a file. Items are matched by their name and
+//! statements by their pretty printed form, so changes to formatting or comments aren't reported.
+
+use std::cmp::max;
+
+use crate::pretty::{fn_signature, pretty_print_expr, pretty_print_item, pretty_print_stmt};
+
+use super::{ElsePart, File, IfStmt, Item, Span, Stmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Fn,
+    Struct,
+    Stmt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Inserted,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub node: NodeKind,
+    /// The span in the old file, unless the node was inserted.
+    pub old: Option<Span>,
+    /// The span in the new file, unless the node was removed.
+    pub new: Option<Span>,
+}
+
+/// The changes from `old` to `new`. Functions only count as modified if their signature or doc
+/// comment changed; changes to their bodies are reported for each statement. Blocks of `if`,
+/// `while` and `loop` statements are compared statement by statement too, as long as the
+/// condition didn't change. Removed items are reported last.
+pub fn diff(old: &File, new: &File) -> Vec<Change> {
+    let mut differ = Differ::default();
+    differ.items(&old.items, &new.items);
+    differ.changes
+}
+
+#[derive(Default)]
+struct Differ {
+    changes: Vec<Change>,
+}
+
+impl Differ {
+    fn push(&mut self, kind: ChangeKind, node: NodeKind, old: Option<&Span>, new: Option<&Span>) {
+        self.changes.push(Change {
+            kind,
+            node,
+            old: old.cloned(),
+            new: new.cloned(),
+        });
+    }
+
+    fn items(&mut self, old: &[Item], new: &[Item]) {
+        let mut matched = vec![false; old.len()];
+        for new_item in new {
+            let old_item = (0..old.len()).find(|&i| {
+                !matched[i] && node_kind(&old[i]) == node_kind(new_item) && {
+                    old[i].name() == new_item.name()
+                }
+            });
+            match old_item {
+                Some(i) => {
+                    matched[i] = true;
+                    self.item(&old[i], new_item);
+                }
+                None => self.push(
+                    ChangeKind::Inserted,
+                    node_kind(new_item),
+                    None,
+                    Some(new_item.span()),
+                ),
+            }
+        }
+        for (old_item, matched) in old.iter().zip(matched) {
+            if !matched {
+                self.push(
+                    ChangeKind::Removed,
+                    node_kind(old_item),
+                    Some(old_item.span()),
+                    None,
+                );
+            }
+        }
+    }
+
+    fn item(&mut self, old: &Item, new: &Item) {
+        match (old, new) {
+            (Item::FnDecl(old_fn), Item::FnDecl(new_fn)) => {
+                if old_fn.doc != new_fn.doc || fn_signature(old_fn) != fn_signature(new_fn) {
+                    self.push(
+                        ChangeKind::Modified,
+                        NodeKind::Fn,
+                        Some(&old_fn.span),
+                        Some(&new_fn.span),
+                    );
+                }
+                self.stmts(&old_fn.body, &new_fn.body);
+            }
+            _ => {
+                if pretty_print_item(old) != pretty_print_item(new) {
+                    self.push(
+                        ChangeKind::Modified,
+                        node_kind(old),
+                        Some(old.span()),
+                        Some(new.span()),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Aligns the statements along their longest common subsequence.
+    fn stmts(&mut self, old: &[Stmt], new: &[Stmt]) {
+        let old_printed = old.iter().map(pretty_print_stmt).collect::<Vec<_>>();
+        let new_printed = new.iter().map(pretty_print_stmt).collect::<Vec<_>>();
+
+        // `common[i][j]` is the length of the longest common subsequence of `old[i..]` and
+        // `new[j..]`.
+        let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                common[i][j] = if old_printed[i] == new_printed[j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    max(common[i + 1][j], common[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        let mut removed = Vec::new();
+        let mut inserted = Vec::new();
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old_printed[i] == new_printed[j] {
+                self.replaced(&removed, &inserted);
+                removed.clear();
+                inserted.clear();
+                i += 1;
+                j += 1;
+            } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+                removed.push(&old[i]);
+                i += 1;
+            } else {
+                inserted.push(&new[j]);
+                j += 1;
+            }
+        }
+        self.replaced(&removed, &inserted);
+    }
+
+    /// Statements that were removed and inserted at the same place are paired up as
+    /// modifications.
+    fn replaced(&mut self, removed: &[&Stmt], inserted: &[&Stmt]) {
+        for (old, new) in removed.iter().zip(inserted) {
+            self.stmt(old, new);
+        }
+        for old in removed.iter().skip(inserted.len()) {
+            self.push(ChangeKind::Removed, NodeKind::Stmt, Some(old.span()), None);
+        }
+        for new in inserted.iter().skip(removed.len()) {
+            self.push(ChangeKind::Inserted, NodeKind::Stmt, None, Some(new.span()));
+        }
+    }
+
+    fn stmt(&mut self, old: &Stmt, new: &Stmt) {
+        match (old, new) {
+            (Stmt::WhileStmt(old_while), Stmt::WhileStmt(new_while))
+                if pretty_print_expr(&old_while.cond) == pretty_print_expr(&new_while.cond) =>
+            {
+                self.stmts(&old_while.body, &new_while.body)
+            }
+            (Stmt::LoopStmt(old_loop), Stmt::LoopStmt(new_loop)) => {
+                self.stmts(&old_loop.body, &new_loop.body)
+            }
+            (Stmt::IfStmt(old_if), Stmt::IfStmt(new_if)) => self.if_stmt(old_if, new_if),
+            _ => self.push(
+                ChangeKind::Modified,
+                NodeKind::Stmt,
+                Some(old.span()),
+                Some(new.span()),
+            ),
+        }
+    }
+
+    fn if_stmt(&mut self, old: &IfStmt, new: &IfStmt) {
+        let same_else = matches!(
+            (&old.else_part, &new.else_part),
+            (None, None)
+                | (Some(ElsePart::Else(..)), Some(ElsePart::Else(..)))
+                | (Some(ElsePart::ElseIf(_)), Some(ElsePart::ElseIf(_)))
+        );
+        if !same_else || pretty_print_expr(&old.cond) != pretty_print_expr(&new.cond) {
+            self.push(
+                ChangeKind::Modified,
+                NodeKind::Stmt,
+                Some(&old.span),
+                Some(&new.span),
+            );
+            return;
+        }
+
+        self.stmts(&old.body, &new.body);
+        match (&old.else_part, &new.else_part) {
+            (Some(ElsePart::Else(old_body, _)), Some(ElsePart::Else(new_body, _))) => {
+                self.stmts(old_body, new_body)
+            }
+            (Some(ElsePart::ElseIf(old_if)), Some(ElsePart::ElseIf(new_if))) => {
+                self.if_stmt(old_if, new_if)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn node_kind(item: &Item) -> NodeKind {
+    match item {
+        Item::FnDecl(_) => NodeKind::Fn,
+        Item::StructDecl(_) => NodeKind::Struct,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, ChangeKind, NodeKind, Span};
+    use crate::{parser::parse, Database, SourceProgram};
+
+    /// The changes with the text of the old and new node instead of their spans.
+    fn changes(old: &str, new: &str) -> Vec<(ChangeKind, NodeKind, String, String)> {
+        let db = Database::default();
+        let parse = |src: &str| {
+            let source = SourceProgram::new(&db, src.to_owned(), "uwu.ub".into());
+            parse(&db, source).expect("test file doesn't parse")
+        };
+        diff(&parse(old), &parse(new))
+            .into_iter()
+            .map(|change| {
+                let text = |src: &str, span: Option<Span>| span.map(|span| src[span].to_owned());
+                (
+                    change.kind,
+                    change.node,
+                    text(old, change.old).unwrap_or_default(),
+                    text(new, change.new).unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    fn change(
+        kind: ChangeKind,
+        node: NodeKind,
+        old: &str,
+        new: &str,
+    ) -> (ChangeKind, NodeKind, String, String) {
+        (kind, node, old.to_owned(), new.to_owned())
+    }
+
+    #[test]
+    fn formatting_is_not_a_change() {
+        let old = "fn main() { let x = 1 + 2; f(x); }";
+        let new = "fn main() {\n    let x = (1 + 2);\n    f(x);\n}\n";
+        assert_eq!(changes(old, new), []);
+    }
+
+    #[test]
+    fn inserted_removed_and_modified_statements() {
+        let old = "fn main() { a; b; c; d; }";
+        assert_eq!(
+            changes(old, "fn main() { a; b; x; c; d; }"),
+            [change(ChangeKind::Inserted, NodeKind::Stmt, "", "x")]
+        );
+        assert_eq!(
+            changes(old, "fn main() { a; c; d; }"),
+            [change(ChangeKind::Removed, NodeKind::Stmt, "b", "")]
+        );
+        // A statement replaced in place is a modification.
+        assert_eq!(
+            changes(old, "fn main() { a; x; c; d; }"),
+            [change(ChangeKind::Modified, NodeKind::Stmt, "b", "x")]
+        );
+    }
+
+    #[test]
+    fn moved_statement_is_inserted_and_removed() {
+        // The longest common subsequence is `a; b;`, so `c;` is new at the start and gone at the
+        // end, and the two aren't paired up.
+        assert_eq!(
+            changes("fn main() { a; b; c; }", "fn main() { c; a; b; }"),
+            [
+                change(ChangeKind::Inserted, NodeKind::Stmt, "", "c"),
+                change(ChangeKind::Removed, NodeKind::Stmt, "c", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_blocks() {
+        assert_eq!(
+            changes(
+                "fn main() { while x { a; b; } if y { c; } else { d; } }",
+                "fn main() { while x { a; e; } if y { c; } else { d; f; } }",
+            ),
+            [
+                change(ChangeKind::Modified, NodeKind::Stmt, "b", "e"),
+                change(ChangeKind::Inserted, NodeKind::Stmt, "", "f"),
+            ]
+        );
+        // A different condition makes the whole statement modified.
+        assert_eq!(
+            changes(
+                "fn main() { while x { a; } }",
+                "fn main() { while z { a; } }"
+            ),
+            [change(
+                ChangeKind::Modified,
+                NodeKind::Stmt,
+                "while x { a; }",
+                "while z { a; }"
+            )]
+        );
+    }
+
+    #[test]
+    fn items() {
+        let old = "struct P { x: u64 } fn f(a: u64) { a; }";
+        let new = "struct P { x: u64, y: u64 } fn f(a: ptr u64) { a; b; }";
+        assert_eq!(
+            changes(old, new),
+            [
+                change(
+                    ChangeKind::Modified,
+                    NodeKind::Struct,
+                    "struct P { x: u64 }",
+                    "struct P { x: u64, y: u64 }"
+                ),
+                change(
+                    ChangeKind::Modified,
+                    NodeKind::Fn,
+                    "fn f(a: u64) { a; }",
+                    "fn f(a: ptr u64) { a; b; }"
+                ),
+                change(ChangeKind::Inserted, NodeKind::Stmt, "", "b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn items_are_matched_by_name_not_position() {
+        assert_eq!(changes("fn a() {} fn b() {}", "fn b() {} fn a() {}"), []);
+        // A renamed item is a new one, and removed items come last.
+        assert_eq!(
+            changes("fn old() { x; } fn b() {}", "fn b() {} fn new() { x; }"),
+            [
+                change(ChangeKind::Inserted, NodeKind::Fn, "", "fn new() { x; }"),
+                change(ChangeKind::Removed, NodeKind::Fn, "fn old() { x; }", ""),
+            ]
+        );
+        // Structs and functions don't match even with the same name.
+        assert_eq!(
+            changes("struct a { x: u64 }", "fn a() {}"),
+            [
+                change(ChangeKind::Inserted, NodeKind::Fn, "", "fn a() {}"),
+                change(
+                    ChangeKind::Removed,
+                    NodeKind::Struct,
+                    "struct a { x: u64 }",
+                    ""
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_names_are_matched_in_order() {
+        // The only new `a` is matched with the first old one, even though it is the same as the
+        // second.
+        assert_eq!(
+            changes("fn a() { x; } fn a() { y; }", "fn a() { y; }"),
+            [
+                change(ChangeKind::Modified, NodeKind::Stmt, "x", "y"),
+                change(ChangeKind::Removed, NodeKind::Fn, "fn a() { y; }", ""),
+            ]
+        );
+        assert_eq!(
+            changes("fn a() { x; }", "fn a() { x; } fn a() { x; }"),
+            [change(
+                ChangeKind::Inserted,
+                NodeKind::Fn,
+                "",
+                "fn a() { x; }"
+            )]
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Finding nodes by position or kind, so that tools don't each need their own walk over the tree.
+//! Nodes come in pre-order: every node before its children, and siblings in source order.
+
+use super::{
+    visit::{self, Visitor},
+    Assignment, Expr, ExprKind, File, FnDecl, IfStmt, LoopStmt, NameTyPair, NodeId, ReturnStmt,
+    Span, StructDecl, Ty, VarDecl, WhileStmt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node<'a> {
+    FnDecl(&'a FnDecl),
+    StructDecl(&'a StructDecl),
+    /// A function parameter or struct field.
+    NameTyPair(&'a NameTyPair),
+    VarDecl(&'a VarDecl),
+    Assignment(&'a Assignment),
+    /// Also the `if` of an `else if`.
+    IfStmt(&'a IfStmt),
+    WhileStmt(&'a WhileStmt),
+    LoopStmt(&'a LoopStmt),
+    ReturnStmt(&'a ReturnStmt),
+    /// The span of a `break`.
+    Break(&'a Span),
+    /// The span of a `continue`.
+    Continue(&'a Span),
+    Expr(&'a Expr),
+    Ty(&'a Ty),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    FnDecl,
+    StructDecl,
+    NameTyPair,
+    VarDecl,
+    Assignment,
+    IfStmt,
+    WhileStmt,
+    LoopStmt,
+    ReturnStmt,
+    Break,
+    Continue,
+    BinOp,
+    UnaryOp,
+    FieldAccess,
+    Call,
+    Literal,
+    Name,
+    Array,
+    Ty,
+}
+
+impl<'a> Node<'a> {
+    pub fn span(&self) -> &'a Span {
+        match *self {
+            Node::FnDecl(fn_decl) => &fn_decl.span,
+            Node::StructDecl(struct_decl) => &struct_decl.span,
+            Node::NameTyPair(pair) => &pair.span,
+            Node::VarDecl(decl) => &decl.span,
+            Node::Assignment(assign) => &assign.span,
+            Node::IfStmt(if_stmt) => &if_stmt.span,
+            Node::WhileStmt(while_stmt) => &while_stmt.span,
+            Node::LoopStmt(loop_stmt) => &loop_stmt.span,
+            Node::ReturnStmt(return_stmt) => &return_stmt.span,
+            Node::Break(span) | Node::Continue(span) => span,
+            Node::Expr(expr) => &expr.span,
+            Node::Ty(ty) => &ty.span,
+        }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        match *self {
+            Node::FnDecl(_) => NodeKind::FnDecl,
+            Node::StructDecl(_) => NodeKind::StructDecl,
+            Node::NameTyPair(_) => NodeKind::NameTyPair,
+            Node::VarDecl(_) => NodeKind::VarDecl,
+            Node::Assignment(_) => NodeKind::Assignment,
+            Node::IfStmt(_) => NodeKind::IfStmt,
+            Node::WhileStmt(_) => NodeKind::WhileStmt,
+            Node::LoopStmt(_) => NodeKind::LoopStmt,
+            Node::ReturnStmt(_) => NodeKind::ReturnStmt,
+            Node::Break(_) => NodeKind::Break,
+            Node::Continue(_) => NodeKind::Continue,
+            Node::Expr(expr) => match expr.kind {
+                ExprKind::BinOp(_) => NodeKind::BinOp,
+                ExprKind::UnaryOp(_) => NodeKind::UnaryOp,
+                ExprKind::FieldAccess(_) => NodeKind::FieldAccess,
+                ExprKind::Call(_) => NodeKind::Call,
+                ExprKind::Literal(_) => NodeKind::Literal,
+                ExprKind::Name(_) => NodeKind::Name,
+                ExprKind::Array(_) => NodeKind::Array,
+            },
+            Node::Ty(_) => NodeKind::Ty,
+        }
+    }
+
+    /// Only declarations and expressions have ids.
+    pub fn id(&self) -> Option<&'a NodeId> {
+        match *self {
+            Node::FnDecl(fn_decl) => Some(&fn_decl.id),
+            Node::StructDecl(struct_decl) => Some(&struct_decl.id),
+            Node::NameTyPair(pair) => Some(&pair.id),
+            Node::Expr(expr) => Some(&expr.id),
+            _ => None,
+        }
+    }
+}
+
+/// Every node of the file.
+pub fn nodes(file: &File) -> Vec<Node<'_>> {
+    let mut collector = Collector::default();
+    visit::walk_items(&mut collector, &file.items);
+    collector.nodes
+}
+
+/// The nodes that lie completely inside `range`.
+pub fn nodes_in_range<'a>(file: &'a File, range: &Span) -> Vec<Node<'a>> {
+    let mut nodes = nodes(file);
+    nodes.retain(|node| range.start <= node.span().start && node.span().end <= range.end);
+    nodes
+}
+
+/// The nodes that contain `offset`, from the outermost to the innermost. A node also counts as
+/// containing its end offset, like names do for [`crate::resolve::Resolutions::def_at`].
+pub fn nodes_at(file: &File, offset: usize) -> Vec<Node<'_>> {
+    let mut nodes = nodes(file);
+    nodes.retain(|node| node.span().start <= offset && offset <= node.span().end);
+    nodes
+}
+
+pub fn find_nodes(file: &File, kind: NodeKind) -> Vec<Node<'_>> {
+    let mut nodes = nodes(file);
+    nodes.retain(|node| node.kind() == kind);
+    nodes
+}
+
+#[derive(Default)]
+struct Collector<'a> {
+    nodes: Vec<Node<'a>>,
+}
+
+impl<'a> Visitor<'a> for Collector<'a> {
+    fn visit_fn_decl(&mut self, fn_decl: &'a FnDecl) {
+        self.nodes.push(Node::FnDecl(fn_decl));
+        visit::walk_fn_decl(self, fn_decl);
+    }
+
+    fn visit_struct_decl(&mut self, struct_decl: &'a StructDecl) {
+        self.nodes.push(Node::StructDecl(struct_decl));
+        visit::walk_struct_decl(self, struct_decl);
+    }
+
+    fn visit_name_ty_pair(&mut self, pair: &'a NameTyPair) {
+        self.nodes.push(Node::NameTyPair(pair));
+        visit::walk_name_ty_pair(self, pair);
+    }
+
+    fn visit_ty(&mut self, ty: &'a Ty) {
+        self.nodes.push(Node::Ty(ty));
+        visit::walk_ty(self, ty);
+    }
+
+    fn visit_var_decl(&mut self, decl: &'a VarDecl) {
+        self.nodes.push(Node::VarDecl(decl));
+        visit::walk_var_decl(self, decl);
+    }
+
+    fn visit_assignment(&mut self, assign: &'a Assignment) {
+        self.nodes.push(Node::Assignment(assign));
+        visit::walk_assignment(self, assign);
+    }
+
+    fn visit_if_stmt(&mut self, if_stmt: &'a IfStmt) {
+        self.nodes.push(Node::IfStmt(if_stmt));
+        visit::walk_if_stmt(self, if_stmt);
+    }
+
+    fn visit_while_stmt(&mut self, while_stmt: &'a WhileStmt) {
+        self.nodes.push(Node::WhileStmt(while_stmt));
+        visit::walk_while_stmt(self, while_stmt);
+    }
+
+    fn visit_loop_stmt(&mut self, loop_stmt: &'a LoopStmt) {
+        self.nodes.push(Node::LoopStmt(loop_stmt));
+        visit::walk_loop_stmt(self, loop_stmt);
+    }
+
+    fn visit_return_stmt(&mut self, return_stmt: &'a ReturnStmt) {
+        self.nodes.push(Node::ReturnStmt(return_stmt));
+        visit::walk_return_stmt(self, return_stmt);
+    }
+
+    fn visit_break(&mut self, span: &'a Span) {
+        self.nodes.push(Node::Break(span));
+    }
+
+    fn visit_continue(&mut self, span: &'a Span) {
+        self.nodes.push(Node::Continue(span));
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        self.nodes.push(Node::Expr(expr));
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_nodes, nodes, nodes_at, nodes_in_range, Node, NodeKind};
+    use crate::{ast::File, parser, Database, SourceProgram};
+
+    const SRC: &str = "fn f(a: u64) { let b = a + 1; b = b * a; }
+struct S { x: ptr u64 }";
+
+    fn parse(db: &Database) -> File {
+        let source = SourceProgram::new(db, SRC.to_owned(), "uwu.ub".into());
+        parser::parse(db, source).expect("no file")
+    }
+
+    /// The kind and source text of each node.
+    fn describe<'a>(nodes: &[Node<'_>]) -> Vec<(NodeKind, &'a str)> {
+        nodes
+            .iter()
+            .map(|node| (node.kind(), &SRC[node.span().clone()]))
+            .collect()
+    }
+
+    #[test]
+    fn pre_order() {
+        let db = Database::default();
+        let file = parse(&db);
+        assert_eq!(
+            describe(&nodes(&file)),
+            [
+                (
+                    NodeKind::FnDecl,
+                    "fn f(a: u64) { let b = a + 1; b = b * a; }"
+                ),
+                (NodeKind::NameTyPair, "a: u64"),
+                (NodeKind::Ty, "u64"),
+                (NodeKind::VarDecl, "let b = a + 1;"),
+                (NodeKind::BinOp, "a + 1"),
+                (NodeKind::Name, "a"),
+                (NodeKind::Literal, "1"),
+                (NodeKind::Assignment, "b = b * a;"),
+                (NodeKind::Name, "b"),
+                (NodeKind::BinOp, "b * a"),
+                (NodeKind::Name, "b"),
+                (NodeKind::Name, "a"),
+                (NodeKind::StructDecl, "struct S { x: ptr u64 }"),
+                (NodeKind::NameTyPair, "x: ptr u64"),
+                (NodeKind::Ty, "ptr u64"),
+                (NodeKind::Ty, "u64"),
+            ]
+        );
+
+        // Siblings and items come in source order.
+        let starts = nodes(&file)
+            .iter()
+            .filter(|node| node.kind() != NodeKind::Ty)
+            .map(|node| node.span().start)
+            .collect::<Vec<_>>();
+        let mut sorted = starts.clone();
+        sorted.sort_unstable();
+        assert_eq!(starts, sorted);
+    }
+
+    #[test]
+    fn range_bounds_are_inclusive() {
+        let db = Database::default();
+        let file = parse(&db);
+        let start = SRC.find("a + 1").unwrap();
+
+        let range = start..start + "a + 1".len();
+        assert_eq!(
+            describe(&nodes_in_range(&file, &range)),
+            [
+                (NodeKind::BinOp, "a + 1"),
+                (NodeKind::Name, "a"),
+                (NodeKind::Literal, "1"),
+            ]
+        );
+
+        // Nodes that only partly lie inside the range aren't included.
+        let range = start + 1..start + "a + 1".len();
+        assert_eq!(
+            describe(&nodes_in_range(&file, &range)),
+            [(NodeKind::Literal, "1")]
+        );
+        assert!(nodes_in_range(&file, &(0..0)).is_empty());
+    }
+
+    #[test]
+    fn nodes_at_go_from_outermost_to_innermost() {
+        let db = Database::default();
+        let file = parse(&db);
+        let offset = SRC.find("* a").unwrap() + 2;
+        assert_eq!(
+            describe(&nodes_at(&file, offset)),
+            [
+                (
+                    NodeKind::FnDecl,
+                    "fn f(a: u64) { let b = a + 1; b = b * a; }"
+                ),
+                (NodeKind::Assignment, "b = b * a;"),
+                (NodeKind::BinOp, "b * a"),
+                (NodeKind::Name, "a"),
+            ]
+        );
+
+        // Nodes also contain their end offset.
+        let offset = SRC.find("a + 1").unwrap() + 1;
+        assert_eq!(
+            describe(&nodes_at(&file, offset)),
+            [
+                (
+                    NodeKind::FnDecl,
+                    "fn f(a: u64) { let b = a + 1; b = b * a; }"
+                ),
+                (NodeKind::VarDecl, "let b = a + 1;"),
+                (NodeKind::BinOp, "a + 1"),
+                (NodeKind::Name, "a"),
+            ]
+        );
+        assert_eq!(
+            describe(&nodes_at(&file, SRC.find("struct").unwrap())),
+            [(NodeKind::StructDecl, "struct S { x: ptr u64 }")]
+        );
+    }
+
+    #[test]
+    fn find_nodes_of_a_kind() {
+        let db = Database::default();
+        let file = parse(&db);
+        assert_eq!(
+            describe(&find_nodes(&file, NodeKind::Name)),
+            [
+                (NodeKind::Name, "a"),
+                (NodeKind::Name, "b"),
+                (NodeKind::Name, "b"),
+                (NodeKind::Name, "a")
+            ]
+        );
+        assert_eq!(
+            describe(&find_nodes(&file, NodeKind::NameTyPair)),
+            [
+                (NodeKind::NameTyPair, "a: u64"),
+                (NodeKind::NameTyPair, "x: ptr u64")
+            ]
+        );
+        assert!(find_nodes(&file, NodeKind::LoopStmt).is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+//! A walk over the tree that tools can hook into where they care, and that visits everything
+//! else for them. Every `walk_` function matches exhaustively, so a new kind of node only needs
+//! to be added here to be visited everywhere.
+
+use super::{
+    Assignment, ElsePart, Expr, ExprKind, FnDecl, IfStmt, Item, LoopStmt, NameTyPair, ReturnStmt,
+    Span, Stmt, StructDecl, Ty, TyKind, VarDecl, WhileStmt,
+};
+
+/// The default of each method walks the children of its node. An overriding method can call the
+/// matching `walk_` function to still visit them.
+pub trait Visitor<'a>: Sized {
+    fn visit_item(&mut self, item: &'a Item) {
+        walk_item(self, item);
+    }
+
+    fn visit_fn_decl(&mut self, fn_decl: &'a FnDecl) {
+        walk_fn_decl(self, fn_decl);
+    }
+
+    fn visit_struct_decl(&mut self, struct_decl: &'a StructDecl) {
+        walk_struct_decl(self, struct_decl);
+    }
+
+    fn visit_name_ty_pair(&mut self, pair: &'a NameTyPair) {
+        walk_name_ty_pair(self, pair);
+    }
+
+    fn visit_ty(&mut self, ty: &'a Ty) {
+        walk_ty(self, ty);
+    }
+
+    fn visit_block(&mut self, stmts: &'a [Stmt]) {
+        walk_block(self, stmts);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_var_decl(&mut self, decl: &'a VarDecl) {
+        walk_var_decl(self, decl);
+    }
+
+    fn visit_assignment(&mut self, assign: &'a Assignment) {
+        walk_assignment(self, assign);
+    }
+
+    /// Also the `if` of an `else if`.
+    fn visit_if_stmt(&mut self, if_stmt: &'a IfStmt) {
+        walk_if_stmt(self, if_stmt);
+    }
+
+    fn visit_while_stmt(&mut self, while_stmt: &'a WhileStmt) {
+        walk_while_stmt(self, while_stmt);
+    }
+
+    fn visit_loop_stmt(&mut self, loop_stmt: &'a LoopStmt) {
+        walk_loop_stmt(self, loop_stmt);
+    }
+
+    fn visit_return_stmt(&mut self, return_stmt: &'a ReturnStmt) {
+        walk_return_stmt(self, return_stmt);
+    }
+
+    /// The span of a `break`.
+    fn visit_break(&mut self, _span: &'a Span) {}
+
+    /// The span of a `continue`.
+    fn visit_continue(&mut self, _span: &'a Span) {}
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk_items<'a, V: Visitor<'a>>(visitor: &mut V, items: &'a [Item]) {
+    for item in items {
+        visitor.visit_item(item);
+    }
+}
+
+pub fn walk_item<'a, V: Visitor<'a>>(visitor: &mut V, item: &'a Item) {
+    match item {
+        Item::FnDecl(fn_decl) => visitor.visit_fn_decl(fn_decl),
+        Item::StructDecl(struct_decl) => visitor.visit_struct_decl(struct_decl),
+    }
+}
+
+pub fn walk_fn_decl<'a, V: Visitor<'a>>(visitor: &mut V, fn_decl: &'a FnDecl) {
+    for param in &fn_decl.params {
+        visitor.visit_name_ty_pair(param);
+    }
+    if let Some(ret_ty) = &fn_decl.ret_ty {
+        visitor.visit_ty(ret_ty);
+    }
+    visitor.visit_block(&fn_decl.body);
+}
+
+pub fn walk_struct_decl<'a, V: Visitor<'a>>(visitor: &mut V, struct_decl: &'a StructDecl) {
+    for field in &struct_decl.fields {
+        visitor.visit_name_ty_pair(field);
+    }
+}
+
+pub fn walk_name_ty_pair<'a, V: Visitor<'a>>(visitor: &mut V, pair: &'a NameTyPair) {
+    visitor.visit_ty(&pair.ty);
+}
+
+pub fn walk_ty<'a, V: Visitor<'a>>(visitor: &mut V, ty: &'a Ty) {
+    match &ty.kind {
+        TyKind::Ptr(inner) => visitor.visit_ty(inner),
+        TyKind::Bool | TyKind::Name(_) => {}
+    }
+}
+
+pub fn walk_block<'a, V: Visitor<'a>>(visitor: &mut V, stmts: &'a [Stmt]) {
+    for stmt in stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'a, V: Visitor<'a>>(visitor: &mut V, stmt: &'a Stmt) {
+    match stmt {
+        Stmt::VarDecl(decl) => visitor.visit_var_decl(decl),
+        Stmt::Assignment(assign) => visitor.visit_assignment(assign),
+        Stmt::IfStmt(if_stmt) => visitor.visit_if_stmt(if_stmt),
+        Stmt::WhileStmt(while_stmt) => visitor.visit_while_stmt(while_stmt),
+        Stmt::LoopStmt(loop_stmt) => visitor.visit_loop_stmt(loop_stmt),
+        Stmt::Return(return_stmt) => visitor.visit_return_stmt(return_stmt),
+        Stmt::Break(span) => visitor.visit_break(span),
+        Stmt::Continue(span) => visitor.visit_continue(span),
+        Stmt::Item(item) => visitor.visit_item(item),
+        Stmt::Expr(expr) => visitor.visit_expr(expr),
+    }
+}
+
+pub fn walk_var_decl<'a, V: Visitor<'a>>(visitor: &mut V, decl: &'a VarDecl) {
+    if let Some(ty) = &decl.ty {
+        visitor.visit_ty(ty);
+    }
+    if let Some(rhs) = &decl.rhs {
+        visitor.visit_expr(rhs);
+    }
+}
+
+pub fn walk_assignment<'a, V: Visitor<'a>>(visitor: &mut V, assign: &'a Assignment) {
+    visitor.visit_expr(&assign.place);
+    visitor.visit_expr(&assign.rhs);
+}
+
+pub fn walk_if_stmt<'a, V: Visitor<'a>>(visitor: &mut V, if_stmt: &'a IfStmt) {
+    visitor.visit_expr(&if_stmt.cond);
+    visitor.visit_block(&if_stmt.body);
+    match &if_stmt.else_part {
+        Some(ElsePart::Else(stmts, _)) => visitor.visit_block(stmts),
+        Some(ElsePart::ElseIf(if_stmt)) => visitor.visit_if_stmt(if_stmt),
+        None => {}
+    }
+}
+
+pub fn walk_while_stmt<'a, V: Visitor<'a>>(visitor: &mut V, while_stmt: &'a WhileStmt) {
+    visitor.visit_expr(&while_stmt.cond);
+    visitor.visit_block(&while_stmt.body);
+}
+
+pub fn walk_loop_stmt<'a, V: Visitor<'a>>(visitor: &mut V, loop_stmt: &'a LoopStmt) {
+    visitor.visit_block(&loop_stmt.body);
+}
+
+pub fn walk_return_stmt<'a, V: Visitor<'a>>(visitor: &mut V, return_stmt: &'a ReturnStmt) {
+    if let Some(expr) = &return_stmt.expr {
+        visitor.visit_expr(expr);
+    }
+}
+
+pub fn walk_expr<'a, V: Visitor<'a>>(visitor: &mut V, expr: &'a Expr) {
+    match &expr.kind {
+        ExprKind::BinOp(bin_op) => {
+            visitor.visit_expr(&bin_op.lhs);
+            visitor.visit_expr(&bin_op.rhs);
+        }
+        ExprKind::UnaryOp(unary_op) => visitor.visit_expr(&unary_op.expr),
+        ExprKind::FieldAccess(field_access) => visitor.visit_expr(&field_access.expr),
+        ExprKind::Call(call) => {
+            visitor.visit_expr(&call.callee);
+            for arg in &call.args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::Array(exprs) => {
+            for expr in exprs {
+                visitor.visit_expr(expr);
+            }
+        }
+        ExprKind::Literal(_) | ExprKind::Name(_) => {}
+    }
+}
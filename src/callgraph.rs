@@ -0,0 +1,216 @@
+//! Which functions call which. Only calls whose callee is the name of a function are known, since
+//! there is no type checker that could tell where other callees point.
+
+use std::{collections::HashSet, fmt::Write};
+
+use crate::{
+    ast::{
+        visit::{self, Visitor},
+        Expr, ExprKind, File, FnDecl,
+    },
+    resolve::{resolve, DefId, DefKind, Resolutions},
+    Db, SourceProgram,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallGraph {
+    /// Every function of the file, including nested ones, in source order.
+    functions: Vec<DefId>,
+    /// Pairs of caller and callee, ordered by the first call. Calling a function more than once
+    /// still only makes one edge.
+    calls: Vec<(DefId, DefId)>,
+}
+
+#[salsa::tracked(return_ref)]
+pub fn call_graph(db: &dyn Db, source: SourceProgram) -> CallGraph {
+    match crate::parser::parse(db, source) {
+        Some(file) => call_graph_of_file(&file, resolve(db, source)),
+        None => CallGraph::default(),
+    }
+}
+
+/// A function of a particular file, to ask the call graph about.
+#[salsa::interned]
+pub struct Function {
+    pub source: SourceProgram,
+    pub def: DefId,
+}
+
+/// The functions that call `function`, in the order of their first call to it.
+#[salsa::tracked(return_ref)]
+pub fn callers_of(db: &dyn Db, function: Function) -> Vec<Function> {
+    let (source, def) = (function.source(db), function.def(db));
+    call_graph(db, source)
+        .calls
+        .iter()
+        .filter(|&&(_, callee)| callee == def)
+        .map(|&(caller, _)| Function::new(db, source, caller))
+        .collect()
+}
+
+/// The functions that `function` calls, in the order of the first call to each.
+#[salsa::tracked(return_ref)]
+pub fn callees_of(db: &dyn Db, function: Function) -> Vec<Function> {
+    let (source, def) = (function.source(db), function.def(db));
+    call_graph(db, source)
+        .calls
+        .iter()
+        .filter(|&&(caller, _)| caller == def)
+        .map(|&(_, callee)| Function::new(db, source, callee))
+        .collect()
+}
+
+pub fn call_graph_of_file(file: &File, resolutions: &Resolutions) -> CallGraph {
+    let mut collector = Collector {
+        resolutions,
+        graph: CallGraph::default(),
+        edges: HashSet::new(),
+        caller: None,
+    };
+    visit::walk_items(&mut collector, &file.items);
+    collector.graph
+}
+
+impl CallGraph {
+    pub fn functions(&self) -> &[DefId] {
+        &self.functions
+    }
+
+    pub fn calls(&self) -> &[(DefId, DefId)] {
+        &self.calls
+    }
+}
+
+/// Renders the call graphs of several files as one Graphviz graph, with a cluster per file.
+pub fn dot<'a>(
+    graphs: impl IntoIterator<Item = (&'a str, &'a CallGraph, &'a Resolutions)>,
+) -> String {
+    let mut out = String::from("digraph calls {\n");
+    for (i, (file_name, graph, resolutions)) in graphs.into_iter().enumerate() {
+        let node = |def: DefId| {
+            let index = graph.functions.iter().position(|&f| f == def);
+            let index = index.expect("call of a function outside the graph");
+            format!("f{i}_{index}")
+        };
+        writeln!(out, "    subgraph cluster_{i} {{").unwrap();
+        writeln!(out, "        label = {};", quote(file_name)).unwrap();
+        for &function in &graph.functions {
+            let name = &resolutions.def(function).name;
+            writeln!(out, "        {} [label = {}];", node(function), quote(name)).unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+        for &(caller, callee) in &graph.calls {
+            writeln!(out, "    {} -> {};", node(caller), node(callee)).unwrap();
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+struct Collector<'a> {
+    resolutions: &'a Resolutions,
+    graph: CallGraph,
+    /// The edges of `graph`, to find duplicates.
+    edges: HashSet<(DefId, DefId)>,
+    /// The function whose body is being walked.
+    caller: Option<DefId>,
+}
+
+impl Visitor<'_> for Collector<'_> {
+    fn visit_fn_decl(&mut self, fn_decl: &FnDecl) {
+        let def = match self.resolutions.def_at(fn_decl.name_span.start) {
+            Some(def) => def,
+            None => return,
+        };
+        self.graph.functions.push(def);
+        // Calls in a nested function are made by that function.
+        let caller = self.caller.replace(def);
+        visit::walk_fn_decl(self, fn_decl);
+        self.caller = caller;
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let (ExprKind::Call(call), Some(caller)) = (&expr.kind, self.caller) {
+            if let ExprKind::Name(_) = call.callee.kind {
+                let callee = self.resolutions.resolved(&call.callee.span);
+                let callee = callee.filter(|&def| self.resolutions.def(def).kind == DefKind::Fn);
+                if let Some(callee) = callee {
+                    if self.edges.insert((caller, callee)) {
+                        self.graph.calls.push((caller, callee));
+                    }
+                }
+            }
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{call_graph, callees_of, callers_of, dot, Function};
+    use crate::{
+        resolve::{resolve, DefKind},
+        Database, SourceProgram,
+    };
+
+    const SRC: &str = "
+fn main() { helper(); other(1); helper(); }
+fn helper() { other(helper); }
+fn other(f: u64) { f(); other(f); let helper = 1; helper(); }
+struct main { x: u64 }
+";
+
+    /// The names of the functions, in order.
+    fn names(db: &Database, functions: &[Function]) -> Vec<String> {
+        functions
+            .iter()
+            .map(|function| {
+                let resolutions = resolve(db, function.source(db));
+                resolutions.def(function.def(db)).name.clone()
+            })
+            .collect()
+    }
+
+    fn function(db: &Database, source: SourceProgram, name: &str) -> Function {
+        let (def, _) = resolve(db, source)
+            .defs()
+            .find(|(_, def)| def.kind == DefKind::Fn && def.name == name)
+            .expect("no such function");
+        Function::new(db, source, def)
+    }
+
+    #[test]
+    fn callers_and_callees() {
+        let db = Database::default();
+        let source = SourceProgram::new(&db, SRC.to_owned(), "uwu.ub".into());
+        let [main, helper, other] = ["main", "helper", "other"].map(|f| function(&db, source, f));
+
+        // Calls of parameters and locals aren't edges, and each edge is only there once.
+        assert_eq!(names(&db, callees_of(&db, main)), ["helper", "other"]);
+        assert_eq!(names(&db, callees_of(&db, helper)), ["other"]);
+        assert_eq!(names(&db, callees_of(&db, other)), ["other"]);
+
+        assert_eq!(names(&db, callers_of(&db, main)), [] as [&str; 0]);
+        assert_eq!(names(&db, callers_of(&db, helper)), ["main"]);
+        assert_eq!(
+            names(&db, callers_of(&db, other)),
+            ["main", "helper", "other"]
+        );
+    }
+
+    #[test]
+    fn dot_output() {
+        let db = Database::default();
+        let source = SourceProgram::new(&db, SRC.to_owned(), "uwu.ub".into());
+        let graph = dot([(
+            "a \"quoted\" file.ub",
+            call_graph(&db, source),
+            resolve(&db, source),
+        )]);
+        insta::assert_snapshot!(graph);
+    }
+}
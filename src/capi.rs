@@ -0,0 +1,61 @@
+//! A C API for embedding the compiler, built with the `capi` feature. The declarations are in
+//! `include/ub.h`. There is no interpreter yet, so programs can only be checked, not run.
+
+use std::{
+    ffi::CString,
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+/// `UbCompilation` in C.
+pub struct Compilation {
+    diagnostics: CString,
+}
+
+/// Returns null if `source` isn't UTF-8 or the compiler panicked, since panics can't unwind into
+/// C.
+///
+/// # Safety
+///
+/// `source` must point to `len` readable bytes. It can be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn ub_compile(source: *const c_char, len: usize) -> *mut Compilation {
+    let bytes = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(source.cast::<u8>(), len)
+    };
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => return ptr::null_mut(),
+    };
+
+    match panic::catch_unwind(AssertUnwindSafe(|| crate::diagnostics_json(text))) {
+        Ok(diagnostics) => {
+            let diagnostics = CString::new(diagnostics).expect("JSON escapes NUL bytes");
+            Box::into_raw(Box::new(Compilation { diagnostics }))
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// The returned string lives as long as the compilation.
+///
+/// # Safety
+///
+/// `compilation` must have been returned by [`ub_compile`] and not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn ub_get_diagnostics(compilation: *const Compilation) -> *const c_char {
+    (*compilation).diagnostics.as_ptr()
+}
+
+/// # Safety
+///
+/// `compilation` must have been returned by [`ub_compile`] and not been freed yet, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn ub_free(compilation: *mut Compilation) {
+    if !compilation.is_null() {
+        drop(Box::from_raw(compilation));
+    }
+}
@@ -0,0 +1,262 @@
+//! HTML documentation for `ub doc`. Every file gets a page listing its items together with their
+//! doc comments, and type names link to the struct of the same name if any of the documented
+//! files declares one.
+
+use std::{collections::HashMap, fmt::Write, path::PathBuf};
+
+use crate::{
+    ast::{File, FnDecl, Item, NameTyPair, StructDecl, Ty, TyKind},
+    highlight,
+};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 50em; margin: 2em auto; line-height: 1.4; }
+section { margin-bottom: 2em; }
+h2 code { font-size: 1.1em; }
+ul.fields { list-style: none; padding-left: 1em; }
+a { color: #3873ad; text-decoration: none; }
+pre { background: #f5f5f5; padding: 0.5em; overflow-x: auto; }";
+
+pub struct Page {
+    /// Relative to the output directory.
+    pub file_name: PathBuf,
+    pub html: String,
+}
+
+/// Renders one page per file plus an `index.html` linking to all of them.
+pub fn document(files: &[File]) -> Vec<Page> {
+    let page_names = files.iter().map(page_name).collect::<Vec<_>>();
+
+    let mut structs = HashMap::new();
+    for (file, page) in files.iter().zip(&page_names) {
+        for item in &file.items {
+            if let Item::StructDecl(struct_decl) = item {
+                structs
+                    .entry(struct_decl.name.as_str())
+                    .or_insert(page.as_str());
+            }
+        }
+    }
+
+    let mut pages = vec![Page {
+        file_name: "index.html".into(),
+        html: index_page(files, &page_names),
+    }];
+
+    for (file, page) in files.iter().zip(&page_names) {
+        let mut writer = PageWriter {
+            out: String::new(),
+            structs: &structs,
+        };
+        writer.file(file);
+        pages.push(Page {
+            file_name: format!("{page}.html").into(),
+            html: writer.out,
+        });
+    }
+
+    pages
+}
+
+/// The name of the page of `file`, without the extension. Files with the same stem get the same
+/// page, and a file named `index` would replace the index.
+pub fn page_name(file: &File) -> String {
+    file.name
+        .file_stem()
+        .unwrap_or(file.name.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn index_page(files: &[File], page_names: &[String]) -> String {
+    let mut out = String::new();
+    header(&mut out, "Documentation");
+    writeln!(out, "<ul>").unwrap();
+    for (file, page) in files.iter().zip(page_names) {
+        writeln!(
+            out,
+            "<li><a href=\"{}.html\">{}</a></li>",
+            escape(page),
+            escape(&file.name.display().to_string())
+        )
+        .unwrap();
+    }
+    writeln!(out, "</ul>").unwrap();
+    footer(&mut out);
+    out
+}
+
+fn header(out: &mut String, title: &str) {
+    writeln!(
+        out,
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<style>
+{STYLE}
+{highlight_style}
+</style>
+</head>
+<body>
+<h1>{title}</h1>",
+        title = escape(title),
+        highlight_style = highlight::STYLE,
+    )
+    .unwrap();
+}
+
+fn footer(out: &mut String) {
+    writeln!(out, "</body>\n</html>").unwrap();
+}
+
+struct PageWriter<'a> {
+    out: String,
+    /// Struct name to the page it is declared on.
+    structs: &'a HashMap<&'a str, &'a str>,
+}
+
+impl PageWriter<'_> {
+    fn file(&mut self, file: &File) {
+        header(&mut self.out, &file.name.display().to_string());
+        writeln!(
+            self.out,
+            "<p><a href=\"index.html\">Back to the index</a></p>"
+        )
+        .unwrap();
+        for item in &file.items {
+            match item {
+                Item::FnDecl(fn_decl) => self.fn_decl(fn_decl),
+                Item::StructDecl(struct_decl) => self.struct_decl(struct_decl),
+            }
+        }
+        footer(&mut self.out);
+    }
+
+    fn fn_decl(&mut self, fn_decl: &FnDecl) {
+        let name = escape(&fn_decl.name);
+        write!(self.out, "<section id=\"fn.{name}\">\n<h2><code>fn {name}(").unwrap();
+        if let [first, rest @ ..] = fn_decl.params.as_slice() {
+            self.name_ty(first);
+            for param in rest {
+                self.out.push_str(", ");
+                self.name_ty(param);
+            }
+        }
+        self.out.push(')');
+        if let Some(ret_ty) = &fn_decl.ret_ty {
+            self.out.push_str(" -&gt; ");
+            self.ty(ret_ty);
+        }
+        writeln!(self.out, "</code></h2>").unwrap();
+        self.doc(&fn_decl.doc);
+        writeln!(self.out, "</section>").unwrap();
+    }
+
+    fn struct_decl(&mut self, struct_decl: &StructDecl) {
+        let name = escape(&struct_decl.name);
+        writeln!(
+            self.out,
+            "<section id=\"struct.{name}\">\n<h2><code>struct {name}</code></h2>"
+        )
+        .unwrap();
+        self.doc(&struct_decl.doc);
+        if !struct_decl.fields.is_empty() {
+            writeln!(self.out, "<ul class=\"fields\">").unwrap();
+            for field in &struct_decl.fields {
+                self.out.push_str("<li><code>");
+                self.name_ty(field);
+                writeln!(self.out, "</code>").unwrap();
+                self.doc(&field.doc);
+                writeln!(self.out, "</li>").unwrap();
+            }
+            writeln!(self.out, "</ul>").unwrap();
+        }
+        writeln!(self.out, "</section>").unwrap();
+    }
+
+    fn name_ty(&mut self, name_ty: &NameTyPair) {
+        write!(self.out, "{}: ", escape(&name_ty.name)).unwrap();
+        self.ty(&name_ty.ty);
+    }
+
+    fn ty(&mut self, ty: &Ty) {
+        match &ty.kind {
+            TyKind::Bool => self.out.push_str("bool"),
+            TyKind::Ptr(inner) => {
+                self.out.push_str("ptr ");
+                self.ty(inner);
+            }
+            TyKind::Name(name) => match self.structs.get(name.as_str()) {
+                Some(page) => write!(
+                    self.out,
+                    "<a href=\"{}.html#struct.{name}\">{name}</a>",
+                    escape(page),
+                    name = escape(name)
+                )
+                .unwrap(),
+                None => self.out.push_str(&escape(name)),
+            },
+        }
+    }
+
+    /// Paragraphs are separated by empty lines, everything else is shown as written. Code blocks
+    /// are fenced by lines of three backticks, and highlighted unless another language than `ub`
+    /// is given.
+    fn doc(&mut self, doc: &Option<String>) {
+        let doc = match doc {
+            Some(doc) => doc,
+            None => return,
+        };
+        let mut lines = Vec::new();
+        // Whether the code block is ub code, if the lines are in one.
+        let mut code = None;
+        for line in doc.lines() {
+            let trimmed = line.trim();
+            if let Some(language) = trimmed.strip_prefix("```") {
+                self.doc_block(&lines, code);
+                lines.clear();
+                code = match code {
+                    Some(_) => None,
+                    None => Some(language.is_empty() || language == "ub"),
+                };
+            } else if code.is_none() && trimmed.is_empty() {
+                self.doc_block(&lines, code);
+                lines.clear();
+            } else {
+                lines.push(line);
+            }
+        }
+        self.doc_block(&lines, code);
+    }
+
+    fn doc_block(&mut self, lines: &[&str], code: Option<bool>) {
+        if lines.is_empty() {
+            return;
+        }
+        let text = lines.join("\n");
+        match code {
+            Some(true) => {
+                let code = highlight::highlight(&text, None);
+                writeln!(self.out, "<pre><code>{code}</code></pre>").unwrap()
+            }
+            Some(false) => writeln!(self.out, "<pre><code>{}</code></pre>", escape(&text)).unwrap(),
+            None => writeln!(self.out, "<p>{}</p>", escape(text.trim())).unwrap(),
+        }
+    }
+}
+
+pub(crate) fn escape(str: &str) -> String {
+    let mut out = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,644 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    env,
+    fmt::Display,
+    fs,
+    io::{self, Read, Write},
+    mem,
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    thread,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use logos::Logos;
+use salsa::ParallelDatabase;
+
+use crate::{
+    ast::File,
+    callgraph, doc,
+    lexer::Token,
+    lint::{self, LintPass},
+    manifest::{self, MANIFEST_FILE_NAME},
+    parser, pretty, resolve, stats, timing, validate, Database, Diagnostics, SourceProgram,
+};
+
+#[derive(Parser)]
+#[clap(
+    name = "ub",
+    version,
+    about = "The ub compiler",
+    after_help = "Pass `-` as the file to read it from standard input. Without any files, \
+                  the files of the project described by the closest `ub.toml` are used."
+)]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+    /// Write a Chrome trace of the compiler's passes and query executions to this path
+    #[clap(long, global = true, value_name = "PATH")]
+    profile: Option<PathBuf>,
+    /// When to use colors in diagnostics. `auto` respects the `NO_COLOR` environment variable
+    #[clap(
+        long,
+        value_enum,
+        global = true,
+        default_value = "auto",
+        value_name = "WHEN"
+    )]
+    color: ColorChoice,
+    /// How to print diagnostics. `json` prints one object per line, for tools
+    #[clap(
+        long,
+        value_enum,
+        global = true,
+        default_value = "human",
+        value_name = "FORMAT"
+    )]
+    error_format: ErrorFormat,
+    /// Print information about the compilation after it finished
+    #[clap(long, value_enum, global = true, value_name = "INFO")]
+    print: Vec<PrintInfo>,
+    /// How many threads to parse files on. Defaults to the number of CPUs
+    #[clap(short = 'j', long, global = true, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+    /// Unstable options for debugging the compiler
+    #[clap(short = 'Z', value_enum, global = true, value_name = "FLAG")]
+    unstable: Vec<UnstableFlag>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// Use colors if stderr, where diagnostics go, is a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                // Has to be the stream that `report_errors` writes to.
+                let no_color = env::var_os("NO_COLOR").filter(|value| !value.is_empty());
+                no_color.is_none() && atty::is(atty::Stream::Stderr)
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PrintInfo {
+    /// AST node counts and peak heap usage
+    MemStats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum UnstableFlag {
+    /// Print how long each phase of the compiler took
+    TimePasses,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check files for errors without emitting anything
+    Check { files: Vec<PathBuf> },
+    /// Print files in their canonical formatting
+    Fmt { files: Vec<PathBuf> },
+    /// Write an intermediate representation of files
+    Emit {
+        #[clap(value_enum)]
+        kind: EmitKind,
+        files: Vec<PathBuf>,
+        /// Write the output to this path instead of deriving it from the input file name.
+        /// Use `-` for stdout
+        #[clap(short = 'o', value_name = "PATH", conflicts_with = "out-dir")]
+        output: Option<PathBuf>,
+        /// Write the output files into this directory
+        #[clap(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+    },
+    /// Generate HTML documentation from the doc comments in files
+    Doc {
+        files: Vec<PathBuf>,
+        /// Write the pages into this directory
+        #[clap(long, value_name = "DIR", default_value = "doc")]
+        out_dir: PathBuf,
+    },
+    /// Print a graph of files in the Graphviz DOT format
+    Graph {
+        #[clap(value_enum)]
+        kind: GraphKind,
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum EmitKind {
+    /// The token stream produced by the lexer
+    Tokens,
+    /// The AST produced by the parser
+    Ast,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum GraphKind {
+    /// Which functions call which, with a cluster for every file
+    Calls,
+}
+
+impl EmitKind {
+    fn extension(self) -> &'static str {
+        match self {
+            EmitKind::Tokens => "tokens",
+            EmitKind::Ast => "ast",
+        }
+    }
+}
+
+const STDIN_FILE_NAME: &str = "<stdin>";
+
+/// Exit code when there were errors in the input.
+const EXIT_ERRORS: u8 = 1;
+/// Exit code when the compiler itself crashed. This is the same code as rustc uses.
+const EXIT_ICE: u8 = 101;
+
+/// The state of one invocation of the driver.
+struct Session {
+    db: Database,
+    /// Whether diagnostics should be colored.
+    color: bool,
+    error_format: ErrorFormat,
+    /// Number of errors reported so far, for the summary at the end.
+    error_count: Cell<usize>,
+    jobs: usize,
+}
+
+impl Session {
+    fn report_error(&self, message: impl Display) {
+        let _ = writeln!(io::stderr(), "error: {message}");
+        self.error_count.set(self.error_count.get() + 1);
+    }
+}
+
+pub fn main() -> ExitCode {
+    main_with_lints(Vec::new())
+}
+
+/// Like [`main`], but `ub check` also runs `lints` on every file that parses.
+pub fn main_with_lints(lints: Vec<Box<dyn LintPass>>) -> ExitCode {
+    let args = Args::parse();
+    let sess = Session {
+        db: Database::with_lint_passes(lints.into_iter().map(Arc::from).collect()),
+        color: args.color.enabled(),
+        error_format: args.error_format,
+        error_count: Cell::new(0),
+        jobs: args
+            .jobs
+            .or_else(|| thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get),
+    };
+
+    let time_passes = args.unstable.contains(&UnstableFlag::TimePasses);
+    if time_passes || args.profile.is_some() {
+        timing::enable();
+    }
+    let mem_stats = args.print.contains(&PrintInfo::MemStats);
+    if mem_stats {
+        stats::enable();
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        timing::time("total", || run(&sess, args.command))
+    }));
+
+    if time_passes {
+        eprint!("{}", timing::report());
+    }
+    if mem_stats {
+        eprint!("{}", stats::report());
+    }
+    if let Some(profile) = args.profile {
+        if let Err(err) = fs::write(&profile, timing::chrome_trace()) {
+            sess.report_error(format_args!(
+                "could not write profile to `{}`: {err}",
+                profile.display()
+            ));
+        }
+    }
+
+    let success = match result {
+        Ok(success) => success,
+        Err(_) => {
+            eprintln!("error: internal compiler error: the compiler unexpectedly panicked");
+            eprintln!("note: this is a bug in ub, please report it");
+            return ExitCode::from(EXIT_ICE);
+        }
+    };
+
+    // Like the diagnostics, this is only lost if stderr was closed.
+    let _ = match sess.error_count.get() {
+        0 if success => return ExitCode::SUCCESS,
+        // Failures should always come with an error, but if one didn't, it still mustn't look
+        // like a success.
+        0 => Ok(()),
+        1 => writeln!(io::stderr(), "error: aborting due to previous error"),
+        count => writeln!(
+            io::stderr(),
+            "error: aborting due to {count} previous errors"
+        ),
+    };
+    ExitCode::from(EXIT_ERRORS)
+}
+
+/// Returns whether the command succeeded.
+fn run(sess: &Session, mut command: Command) -> bool {
+    let db = &sess.db;
+
+    let files = match &mut command {
+        Command::Check { files }
+        | Command::Fmt { files }
+        | Command::Emit { files, .. }
+        | Command::Doc { files, .. }
+        | Command::Graph { files, .. } => files,
+    };
+    *files = match input_files(sess, mem::take(files)) {
+        Some(files) => files,
+        None => return false,
+    };
+    match command {
+        Command::Check { files } => for_each_source(sess, files, true, |source| {
+            parse(sess, source)?;
+            timing::time("validate", || validate::validate(db, source));
+            timing::time("lint", || lint::lint(db, source));
+            let mut errs = validate::validate::accumulated::<Diagnostics>(db, source);
+            errs.extend(lint::lint::accumulated::<Diagnostics>(db, source));
+            report_diagnostics(sess, source, errs)
+        }),
+        Command::Fmt { files } => for_each_source(sess, files, true, |source| {
+            let ast = parse(sess, source)?;
+            let formatted = timing::time("pretty print", || {
+                pretty::pretty_print_ast_with_comments(&ast, source.text(db))
+            });
+            print!("{formatted}");
+            Some(())
+        }),
+        Command::Emit {
+            kind,
+            files,
+            output,
+            out_dir,
+        } => {
+            if output.is_some() && files.len() > 1 {
+                sess.report_error(
+                    "`-o` can only be used with a single input file, use `--out-dir` instead",
+                );
+                return false;
+            }
+            // Inputs with the same stem in different directories get the same output file.
+            let mut written = HashMap::new();
+            for_each_source(sess, files, kind == EmitKind::Ast, |source| {
+                let content = emit(sess, source, kind)?;
+                let destination =
+                    destination(db, source, kind, output.as_deref(), out_dir.as_deref());
+                if let Destination::File(path) = &destination {
+                    let file_name = source.file_name(db);
+                    if let Some(first) = written.insert(path.clone(), file_name) {
+                        sess.report_error(format_args!(
+                            "`{}` and `{}` would both be written to `{}`",
+                            first.display(),
+                            file_name.display(),
+                            path.display()
+                        ));
+                        return None;
+                    }
+                }
+                write_output(sess, destination, &content)
+            })
+        }
+        Command::Doc { files, out_dir } => {
+            // All files are needed up front so that types can link across pages.
+            let mut asts = Vec::new();
+            let mut success = for_each_source(sess, files, true, |source| {
+                asts.push(parse(sess, source)?);
+                Some(())
+            });
+            if !success {
+                return false;
+            }
+
+            let mut page_files = HashMap::from([("index".to_string(), None)]);
+            for ast in &asts {
+                let page = doc::page_name(ast);
+                let path = out_dir.join(format!("{page}.html"));
+                match page_files.insert(page, Some(&ast.name)) {
+                    Some(Some(first)) => sess.report_error(format_args!(
+                        "`{}` and `{}` would both be documented in `{}`",
+                        first.display(),
+                        ast.name.display(),
+                        path.display()
+                    )),
+                    Some(None) => sess.report_error(format_args!(
+                        "`{}` would be documented in `{}`, which is the index",
+                        ast.name.display(),
+                        path.display()
+                    )),
+                    None => continue,
+                }
+                success = false;
+            }
+            if !success {
+                return false;
+            }
+
+            let pages = timing::time("document", || doc::document(&asts));
+            if let Err(err) = fs::create_dir_all(&out_dir) {
+                sess.report_error(format_args!(
+                    "could not create `{}`: {err}",
+                    out_dir.display()
+                ));
+                return false;
+            }
+            let mut success = true;
+            for page in pages {
+                let destination = Destination::File(out_dir.join(page.file_name));
+                success &= write_output(sess, destination, &page.html).is_some();
+            }
+            success
+        }
+        Command::Graph {
+            kind: GraphKind::Calls,
+            files,
+        } => {
+            let mut sources = Vec::new();
+            let success = for_each_source(sess, files, true, |source| {
+                parse(sess, source)?;
+                sources.push(source);
+                Some(())
+            });
+            if !success {
+                return false;
+            }
+
+            let file_names = sources
+                .iter()
+                .map(|source| source.file_name(db).to_string_lossy())
+                .collect::<Vec<_>>();
+            let graph = timing::time("call graph", || {
+                callgraph::dot(sources.iter().zip(&file_names).map(|(&source, file_name)| {
+                    let graph = callgraph::call_graph(db, source);
+                    (&**file_name, graph, resolve::resolve(db, source))
+                }))
+            });
+            print!("{graph}");
+            true
+        }
+    }
+}
+
+/// Returns the files passed on the command line, or the files of the project in the current
+/// directory if there were none.
+fn input_files(sess: &Session, files: Vec<PathBuf>) -> Option<Vec<PathBuf>> {
+    if !files.is_empty() {
+        return Some(files);
+    }
+
+    let manifest_path = match env::current_dir().ok().and_then(|dir| manifest::find(&dir)) {
+        Some(path) => path,
+        None => {
+            sess.report_error(format_args!(
+                "no input files given and no `{MANIFEST_FILE_NAME}` found"
+            ));
+            return None;
+        }
+    };
+
+    let manifest = match manifest::load(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            sess.report_error(format_args!(
+                "could not load `{}`: {err}",
+                manifest_path.display()
+            ));
+            return None;
+        }
+    };
+
+    let root = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    match manifest.source_files(root) {
+        Ok(files) => Some(files),
+        Err(err) => {
+            sess.report_error(format_args!(
+                "could not collect the source files of `{}`: {err}",
+                manifest.package.name
+            ));
+            None
+        }
+    }
+}
+
+/// Reads all files and runs `action` on each of them. Keeps going after failures so that
+/// errors from every file get reported. Returns whether everything succeeded. If `action` parses
+/// the files, they are parsed in parallel up front.
+fn for_each_source(
+    sess: &Session,
+    file_names: Vec<PathBuf>,
+    parses: bool,
+    mut action: impl FnMut(SourceProgram) -> Option<()>,
+) -> bool {
+    let sources = file_names
+        .into_iter()
+        .map(|file_name| read_source(sess, file_name))
+        .collect::<Vec<_>>();
+    if parses {
+        let sources = sources.iter().flatten().copied().collect::<Vec<_>>();
+        timing::time("parse in parallel", || parse_in_parallel(sess, &sources));
+    }
+
+    let mut success = true;
+    for source in sources {
+        success &= source.and_then(&mut action).is_some();
+    }
+    success
+}
+
+/// Fills the query cache so that `action` doesn't have to wait for parsing. Each thread gets its own
+/// snapshot of the database. The diagnostics are still reported in order by `action`.
+fn parse_in_parallel(sess: &Session, sources: &[SourceProgram]) {
+    let threads = sess.jobs.min(sources.len());
+    if threads <= 1 {
+        return;
+    }
+
+    let handles = (0..threads)
+        .map(|i| {
+            let db = sess.db.snapshot();
+            let sources = sources.iter().skip(i).step_by(threads).copied();
+            let sources = sources.collect::<Vec<_>>();
+            thread::spawn(move || {
+                for source in sources {
+                    parser::parse(&*db, source);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        if let Err(panic) = handle.join() {
+            panic::resume_unwind(panic);
+        }
+    }
+}
+
+/// Reads the file at `file_name`, or standard input if it is `-`.
+fn read_source(sess: &Session, file_name: PathBuf) -> Option<SourceProgram> {
+    timing::time("read", || read_source_inner(sess, file_name))
+}
+
+fn read_source_inner(sess: &Session, file_name: PathBuf) -> Option<SourceProgram> {
+    let db = &sess.db;
+    if file_name.as_os_str() == "-" {
+        let mut text = String::new();
+        return match io::stdin().read_to_string(&mut text) {
+            Ok(_) => Some(SourceProgram::new(db, text, STDIN_FILE_NAME.into())),
+            Err(err) => {
+                sess.report_error(format_args!("could not read standard input: {err}"));
+                None
+            }
+        };
+    }
+
+    match fs::read_to_string(&file_name) {
+        Ok(text) => Some(SourceProgram::new(db, text, file_name)),
+        Err(err) => {
+            sess.report_error(format_args!(
+                "could not read `{}`: {err}",
+                file_name.display()
+            ));
+            None
+        }
+    }
+}
+
+/// Parses the source and reports all errors. Only returns the AST if there were no errors.
+fn parse(sess: &Session, source: SourceProgram) -> Option<File> {
+    let db = &sess.db;
+    let file = parser::parse(db, source);
+    let errs = parser::parse::accumulated::<Diagnostics>(db, source);
+
+    if let Some(file) = &file {
+        stats::count_ast(file);
+    }
+
+    report_diagnostics(sess, source, errs)?;
+    file
+}
+
+/// Returns `None` if there were any errors.
+fn report_diagnostics(
+    sess: &Session,
+    source: SourceProgram,
+    errs: Vec<parser::Error>,
+) -> Option<()> {
+    if errs.is_empty() {
+        return Some(());
+    }
+
+    let db = &sess.db;
+    sess.error_count.set(sess.error_count.get() + errs.len());
+    let (file_name, text) = (source.file_name(db), source.text(db));
+    // If stderr was closed, like by `ub check 2>&1 | head`, there is nowhere left to report
+    // anything to. The exit code still tells that there were errors.
+    let _ = timing::time("render diagnostics", || match sess.error_format {
+        ErrorFormat::Human => crate::report_errors(file_name, text, errs, sess.color),
+        ErrorFormat::Json => crate::report_errors_json(file_name, text, &errs),
+    });
+    None
+}
+
+fn emit(sess: &Session, source: SourceProgram, kind: EmitKind) -> Option<String> {
+    let db = &sess.db;
+    match kind {
+        EmitKind::Tokens => Some(timing::time("emit tokens", || {
+            Token::lexer(source.text(db))
+                .spanned()
+                .map(|(token, span)| format!("{span:?} {token:?}\n"))
+                .collect()
+        })),
+        EmitKind::Ast => {
+            let ast = parse(sess, source)?;
+            Some(timing::time("emit ast", || format!("{ast:#?}\n")))
+        }
+    }
+}
+
+enum Destination {
+    Stdout,
+    File(PathBuf),
+}
+
+/// Picks where the output of `emit` goes. An explicit `-o` wins, otherwise the file is named
+/// after the input with the extension of the emit kind. Input from stdin goes back to stdout.
+fn destination(
+    db: &Database,
+    source: SourceProgram,
+    kind: EmitKind,
+    output: Option<&Path>,
+    out_dir: Option<&Path>,
+) -> Destination {
+    if let Some(output) = output {
+        return if output.as_os_str() == "-" {
+            Destination::Stdout
+        } else {
+            Destination::File(output.to_owned())
+        };
+    }
+
+    let file_name = source.file_name(db);
+    let stem = if file_name.as_os_str() == STDIN_FILE_NAME {
+        if out_dir.is_none() {
+            return Destination::Stdout;
+        }
+        "stdin".as_ref()
+    } else {
+        file_name.file_stem().unwrap_or(file_name.as_os_str())
+    };
+    let path = Path::new(stem).with_extension(kind.extension());
+
+    Destination::File(match out_dir {
+        Some(out_dir) => out_dir.join(path),
+        None => path,
+    })
+}
+
+fn write_output(sess: &Session, destination: Destination, content: &str) -> Option<()> {
+    timing::time("write output", || {
+        write_output_inner(sess, destination, content)
+    })
+}
+
+fn write_output_inner(sess: &Session, destination: Destination, content: &str) -> Option<()> {
+    match destination {
+        Destination::Stdout => {
+            print!("{content}");
+            Some(())
+        }
+        Destination::File(path) => match fs::write(&path, content) {
+            Ok(()) => Some(()),
+            Err(err) => {
+                sess.report_error(format_args!("could not write `{}`: {err}", path.display()));
+                None
+            }
+        },
+    }
+}
@@ -0,0 +1,17 @@
+//! Entry points for the fuzz targets in `fuzz/`. Both take arbitrary bytes. Invalid UTF-8 is
+//! replaced, since files that aren't UTF-8 are rejected before they are lexed.
+
+use crate::{lexer, parser, Database, Diagnostics, SourceProgram};
+
+pub fn lex(data: &[u8]) {
+    let text = String::from_utf8_lossy(data);
+    lexer::lex(&text).for_each(drop);
+}
+
+pub fn parse(data: &[u8]) {
+    let db = Database::default();
+    let text = String::from_utf8_lossy(data).into_owned();
+    let source = SourceProgram::new(&db, text, "fuzz.ub".into());
+    parser::parse(&db, source);
+    parser::parse::accumulated::<Diagnostics>(&db, source);
+}
@@ -0,0 +1,131 @@
+//! Syntax highlighting as HTML, for the documentation and the playground. Tokens are wrapped in
+//! `<span>`s with a class for their kind, see [`STYLE`].
+
+use std::fmt::Write;
+
+use crate::{
+    doc::escape,
+    lexer::{self, Token},
+    resolve::{DefKind, Resolutions},
+};
+
+/// Colors for the classes used by [`highlight`].
+pub const STYLE: &str = "\
+.keyword { color: #8959a8; }
+.string { color: #718c00; }
+.number { color: #f5871f; }
+.comment, .doc-comment { color: #8e908c; }
+.fn { color: #4271ae; }
+.struct { color: #3e999f; }
+.field, .param, .local { color: #c82829; }
+.error { text-decoration: red wavy underline; }";
+
+/// Returns `code` as HTML, without a surrounding `<pre>`. Names are only highlighted if
+/// `resolutions` for `code` are given, depending on what they refer to.
+pub fn highlight(code: &str, resolutions: Option<&Resolutions>) -> String {
+    let names = match resolutions {
+        Some(resolutions) => resolutions
+            .names()
+            .into_iter()
+            .map(|(span, def, _)| (span, resolutions.def(def).kind))
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut names = names.into_iter().peekable();
+
+    let mut out = String::with_capacity(code.len());
+    let mut end = 0;
+    for (token, span) in lexer::lex(code).spanned() {
+        gap(&mut out, &code[end..span.start]);
+        end = span.end;
+
+        let class = match token {
+            Token::Struct
+            | Token::Fn
+            | Token::If
+            | Token::Else
+            | Token::While
+            | Token::Loop
+            | Token::Ptr
+            | Token::Let
+            | Token::Return
+            | Token::Break
+            | Token::Continue
+            | Token::True
+            | Token::False => Some("keyword"),
+            Token::String(_) | Token::UnterminatedString(_) => Some("string"),
+            Token::Integer(_) => Some("number"),
+            Token::DocComment(_) => Some("doc-comment"),
+            Token::Error => Some("error"),
+            Token::Ident(_) => {
+                while names.next_if(|(name, _)| name.start < span.start).is_some() {}
+                names
+                    .next_if(|(name, _)| name.start == span.start)
+                    .map(|(_, kind)| def_class(kind))
+            }
+            _ => None,
+        };
+        let text = escape(&code[span]);
+        match class {
+            Some(class) => write!(out, "<span class=\"{class}\">{text}</span>").unwrap(),
+            None => out.push_str(&text),
+        }
+    }
+    gap(&mut out, &code[end..]);
+    out
+}
+
+/// The text between two tokens, which can only contain whitespace and comments.
+fn gap(out: &mut String, mut text: &str) {
+    while let Some(start) = text.find("//") {
+        out.push_str(&escape(&text[..start]));
+        let end = text[start..]
+            .find('\n')
+            .map_or(text.len(), |len| start + len);
+        let comment = escape(&text[start..end]);
+        write!(out, "<span class=\"comment\">{comment}</span>").unwrap();
+        text = &text[end..];
+    }
+    out.push_str(&escape(text));
+}
+
+fn def_class(kind: DefKind) -> &'static str {
+    match kind {
+        DefKind::Fn => "fn",
+        DefKind::Struct => "struct",
+        DefKind::Field => "field",
+        DefKind::Param => "param",
+        DefKind::Local => "local",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::highlight;
+    use crate::{resolve::resolve, Database, SourceProgram};
+
+    const CODE: &str = r#"//// Banner <b>
+/// A point & more.
+struct Point { x: u64 }
+
+fn main(p: ptr Point) -> bool {
+    let s = "a < b"; // trailing
+    print(s, 42, p);
+    return true;
+}
+"#;
+
+    #[test]
+    fn with_resolutions() {
+        let db = Database::default();
+        let source = SourceProgram::new(&db, CODE.to_owned(), "uwu.ub".into());
+        let html = highlight(CODE, Some(resolve(&db, source)));
+        insta::assert_snapshot!(html);
+    }
+
+    #[test]
+    fn without_resolutions() {
+        let html = highlight("let x = \"open\n@ 1; // done", None);
+        insta::assert_snapshot!(html);
+    }
+}
@@ -7,6 +7,10 @@ pub enum Token {
     #[regex("//[^\n]*", logos::skip)]
     Comment,
 
+    /// `////` starts a normal comment, like in Rust, so that banners made of slashes aren't docs.
+    #[regex("///([^/\n][^\n]*)?", |lex| lex.slice()[3..].to_string())]
+    DocComment(String),
+
     // punctuation
     #[token("{")]
     BraceO,
@@ -46,6 +50,8 @@ pub enum Token {
     Asterisk,
     #[token("/")]
     Slash,
+    #[token("%")]
+    Percent,
     #[token("+")]
     Plus,
     #[token("-")]
@@ -82,13 +88,28 @@ pub enum Token {
     Ptr,
     #[token("let")]
     Let,
+    #[token("return")]
+    Return,
+    #[token("break")]
+    Break,
+    #[token("continue")]
+    Continue,
+    #[token("true")]
+    True,
+    #[token("false")]
+    False,
 
     #[regex(r"[a-zA-Z_]\w*", |lex| lex.slice().to_string())]
     Ident(String),
 
-    #[regex(r##""[^"]*""##, |lex| lex.slice().to_string())]
+    #[regex(r##""[^"\n]*""##, |lex| lex.slice().to_string())]
     String(String),
 
+    /// A string that runs into the end of its line. The parser reports it and reads it as if it
+    /// was closed there, so that a missing `"` doesn't swallow the rest of the file.
+    #[regex(r##""[^"\n]*"##, |lex| lex.slice().to_string())]
+    UnterminatedString(String),
+
     #[regex(r"\d+", |lex| lex.slice().parse())]
     Integer(u64),
 
@@ -101,6 +122,7 @@ impl Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Token::Comment => f.write_str("comment"),
+            Token::DocComment(_) => f.write_str("doc comment"),
             Token::BraceO => f.write_str("{"),
             Token::BraceC => f.write_str("}"),
             Token::BracketO => f.write_str("["),
@@ -120,6 +142,7 @@ impl Display for Token {
             Token::LessEq => f.write_str("<="),
             Token::Asterisk => f.write_str("*"),
             Token::Slash => f.write_str("/"),
+            Token::Percent => f.write_str("%"),
             Token::Plus => f.write_str("+"),
             Token::Minus => f.write_str("-"),
             Token::Or => f.write_str("|"),
@@ -137,8 +160,14 @@ impl Display for Token {
             Token::Loop => f.write_str("loop"),
             Token::Ptr => f.write_str("ptr"),
             Token::Let => f.write_str("let"),
+            Token::Return => f.write_str("return"),
+            Token::Break => f.write_str("break"),
+            Token::Continue => f.write_str("continue"),
+            Token::True => f.write_str("true"),
+            Token::False => f.write_str("false"),
             Token::Ident(ident) => write!(f, "identifier `{ident}`"),
             Token::String(str) => write!(f, "\"{str}\""),
+            Token::UnterminatedString(_) => f.write_str("unterminated string"),
             Token::Integer(int) => write!(f, "{int}"),
             Token::Error => f.write_str("error"),
         }
@@ -160,7 +189,7 @@ mod tests {
 
     #[test]
     fn punctuation() {
-        let tokens = lex_test("{} [] () .,; = == != >= <= < > + - * / | || & && ^ -> :");
+        let tokens = lex_test("{} [] () .,; = == != >= <= < > + - * / % | || & && ^ -> :");
         insta::assert_debug_snapshot!(tokens);
     }
 
@@ -185,6 +214,30 @@ mod tests {
         insta::assert_debug_snapshot!(tokens);
     }
 
+    #[test]
+    fn unterminated_string() {
+        let tokens = lex_test("\"open\nfn \"closed\" \"");
+        insta::assert_debug_snapshot!(tokens);
+    }
+
+    #[test]
+    fn bools() {
+        let tokens = lex_test("true false bool trueish");
+        insta::assert_debug_snapshot!(tokens);
+    }
+
+    #[test]
+    fn comments() {
+        let tokens = lex_test(
+            "// skipped
+/// documentation
+///
+//// banner
+fn",
+        );
+        insta::assert_debug_snapshot!(tokens);
+    }
+
     #[test]
     fn keywords() {
         let tokens = lex_test("struct fn . if else while loop;");
@@ -1,15 +1,44 @@
 #![warn(rust_2018_idioms)]
 #![allow(dead_code)]
 
-use std::path::PathBuf;
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use ariadne::{Color, Fmt, Label, Report, ReportKind, Source};
+use ariadne::{Color, Config, Fmt, Label, Report, ReportKind, Source};
+use line_index::LineIndex;
+use lint::LintPass;
 use parser::Error;
+use salsa::DebugWithDb;
+use serde::Serialize;
 
-mod ast;
+pub mod ast;
+pub mod callgraph;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod doc;
+pub mod driver;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+mod highlight;
 mod lexer;
+mod line_index;
+pub mod lint;
+pub mod lsp;
+mod manifest;
 mod parser;
 mod pretty;
+pub mod resolve;
+#[cfg(test)]
+mod roundtrip;
+mod stats;
+mod timing;
+mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 #[salsa::input]
 pub struct SourceProgram {
@@ -20,83 +49,237 @@ pub struct SourceProgram {
 }
 
 #[salsa::jar(db = Db)]
-pub struct Jar(SourceProgram, Diagnostics, crate::parser::parse);
+pub struct Jar(
+    SourceProgram,
+    Diagnostics,
+    crate::parser::parse,
+    crate::line_index::line_index,
+    crate::resolve::resolve,
+    crate::resolve::references,
+    crate::callgraph::call_graph,
+    crate::callgraph::Function,
+    crate::callgraph::callers_of,
+    crate::callgraph::callees_of,
+    crate::lint::lint,
+    crate::validate::validate,
+);
 
-pub trait Db: salsa::DbWithJar<Jar> {}
+pub trait Db: salsa::DbWithJar<Jar> {
+    /// Not tracked, so it must not change after the database was created.
+    fn lint_passes(&self) -> &[Arc<dyn LintPass>];
+}
 
-impl<DB> Db for DB where DB: ?Sized + salsa::DbWithJar<Jar> {}
+impl Db for Database {
+    fn lint_passes(&self) -> &[Arc<dyn LintPass>] {
+        &self.lint_passes
+    }
+}
 
 #[salsa::accumulator]
 pub struct Diagnostics(Error);
 
+/// The diagnostics of a file that appeared or went away between two revisions. A diagnostic that
+/// is reported twice has to go away twice to count as fixed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticsDelta<D> {
+    /// In the order of the new revision.
+    pub introduced: Vec<D>,
+    /// In the order of the old revision.
+    pub fixed: Vec<D>,
+}
+
+impl<D: PartialEq + Clone> DiagnosticsDelta<D> {
+    pub fn new(old: &[D], new: &[D]) -> Self {
+        let mut fixed = old.iter().map(Some).collect::<Vec<_>>();
+        let mut introduced = Vec::new();
+        for diagnostic in new {
+            match fixed.iter_mut().find(|old| *old == &Some(diagnostic)) {
+                Some(old) => *old = None,
+                None => introduced.push(diagnostic.clone()),
+            }
+        }
+        DiagnosticsDelta {
+            introduced,
+            fixed: fixed.into_iter().flatten().cloned().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.introduced.is_empty() && self.fixed.is_empty()
+    }
+}
+
 #[derive(Default)]
 #[salsa::db(crate::Jar)]
 pub(crate) struct Database {
     storage: salsa::Storage<Self>,
+    query_log: Option<Arc<QueryLog>>,
+    lint_passes: Vec<Arc<dyn LintPass>>,
 }
 
-impl salsa::Database for Database {}
+type QueryLog = dyn Fn(QueryEvent, &str) + Send + Sync;
 
-// aaa
-
-pub fn test() {
-    let src = "
-fn main(uwu: u64, owo: ptr WOW) -> ptr u64 {
-    let uwu = &1;
-    let owo = yeet(1+2*3, AA);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum QueryEvent {
+    Executed,
+    /// The memoized result was checked against the inputs that changed and could be reused.
+    Validated,
+}
 
-    if 1 {
-        10;
-    } else {}
+impl Database {
+    pub(crate) fn with_lint_passes(lint_passes: Vec<Arc<dyn LintPass>>) -> Self {
+        Database {
+            lint_passes,
+            ..Database::default()
+        }
+    }
 
-    if 1 { if 1 { if 1 {} } }
+    /// Calls `log` with the name of every query that is executed or validated. Queries that were
+    /// already validated in the current revision aren't reported.
+    pub(crate) fn set_query_log(&mut self, log: impl Fn(QueryEvent, &str) + Send + Sync + 'static) {
+        self.query_log = Some(Arc::new(log));
+    }
 }
 
-fn aa() {}
-";
+impl salsa::Database for Database {
+    fn salsa_event(&self, event: salsa::Event) {
+        let (query_event, database_key) = match event.kind {
+            salsa::EventKind::WillExecute { database_key } => (QueryEvent::Executed, database_key),
+            salsa::EventKind::DidValidateMemoizedValue { database_key } => {
+                (QueryEvent::Validated, database_key)
+            }
+            _ => return,
+        };
+        if query_event == QueryEvent::Executed {
+            timing::query_executed(|| format!("{:?}", database_key.debug(self)));
+        }
+        if let Some(log) = &self.query_log {
+            log(query_event, &format!("{:?}", database_key.debug(self)));
+        }
+    }
+}
 
-    let db = Database::default();
-    let source_program = SourceProgram::new(&db, src.to_string(), "uwu.ub".into());
+impl salsa::ParallelDatabase for Database {
+    fn snapshot(&self) -> salsa::Snapshot<Self> {
+        salsa::Snapshot::new(Database {
+            storage: self.storage.snapshot(),
+            query_log: self.query_log.clone(),
+            lint_passes: self.lint_passes.clone(),
+        })
+    }
+}
 
-    let file = parser::parse(&db, source_program);
+#[derive(Serialize)]
+struct JsonDiagnostic {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<PathBuf>,
+    message: String,
+    start: JsonPosition,
+    end: JsonPosition,
+}
 
-    if let Some(file) = file {
-        println!("{}", pretty::pretty_print_ast(&file));
+impl JsonDiagnostic {
+    fn new(file: Option<&Path>, text: &str, index: &LineIndex, err: &Error) -> Self {
+        let position = |offset| {
+            let line_col = index.line_col(text, offset);
+            JsonPosition {
+                line: line_col.line,
+                column: line_col.col,
+                offset,
+            }
+        };
+        JsonDiagnostic {
+            file: file.map(Path::to_owned),
+            message: err.message(),
+            start: position(err.span().start),
+            end: position(err.span().end),
+        }
     }
+}
+
+/// `line` and `column` are zero based, and `column` counts UTF-16 code units like JavaScript
+/// string indices. `offset` is in bytes.
+#[derive(Serialize)]
+struct JsonPosition {
+    line: u32,
+    column: u32,
+    offset: usize,
+}
+
+/// Checks `text` and returns the errors as a JSON array of `{ message, start, end }`, for the
+/// bindings that can't render reports.
+fn diagnostics_json(text: String) -> String {
+    let db = Database::default();
+    let source = SourceProgram::new(&db, text, "input.ub".into());
+    validate::validate(&db, source);
 
-    let errs = parser::parse::accumulated::<Diagnostics>(&db, source_program);
+    let text = source.text(&db);
+    let index = LineIndex::new(text);
+    // Accumulated values are collected from all queries that a query calls, which includes the
+    // syntax errors from `parse`.
+    let diagnostics = validate::validate::accumulated::<Diagnostics>(&db, source)
+        .iter()
+        .map(|err| JsonDiagnostic::new(None, text, &index, err))
+        .collect::<Vec<_>>();
+    serde_json::to_string(&diagnostics).expect("diagnostics can always be serialized")
+}
 
-    report_errors(src, errs);
+/// Prints one JSON object per error to stderr, in the format of [`diagnostics_json`] but with
+/// the file name.
+fn report_errors_json(file_name: &Path, src: &str, errors: &[parser::Error]) -> io::Result<()> {
+    let index = LineIndex::new(src);
+    let mut stderr = io::stderr().lock();
+    for err in errors {
+        let diagnostic = JsonDiagnostic::new(Some(file_name), src, &index, err);
+        let json =
+            serde_json::to_string(&diagnostic).expect("diagnostics can always be serialized");
+        writeln!(stderr, "{json}")?;
+    }
+    Ok(())
 }
 
-fn report_errors(src: &str, errors: Vec<parser::Error>) {
+/// Renders the errors to stderr, like [`report_errors_json`], so that they don't end up in the
+/// output of commands that write to stdout.
+fn report_errors(
+    file_name: &Path,
+    src: &str,
+    errors: Vec<parser::Error>,
+    color: bool,
+) -> io::Result<()> {
+    let file_name = file_name.display().to_string();
+    let paint = |text: &dyn Display, fg: Color| {
+        if color {
+            text.fg(fg).to_string()
+        } else {
+            text.to_string()
+        }
+    };
     errors
         .into_iter()
-        .map(|e| e.0.map(|c| c.to_string()))
-        .for_each(|e| {
-            let report = Report::build(ReportKind::Error, (), e.span().start);
+        .map(|e| (e.expected(), e.0.map(|c| c.to_string())))
+        .try_for_each(|(expected, e)| {
+            let report = Report::build(ReportKind::Error, file_name.clone(), e.span().start)
+                .with_config(Config::default().with_color(color));
 
             let report = match e.reason() {
                 chumsky::error::SimpleReason::Unclosed { span, delimiter } => report
                     .with_message(format!(
                         "Unclosed delimiter {}",
-                        delimiter.fg(Color::Yellow)
+                        paint(delimiter, Color::Yellow)
                     ))
                     .with_label(
-                        Label::new(span.clone())
+                        Label::new((file_name.clone(), span.clone()))
                             .with_message(format!(
                                 "Unclosed delimiter {}",
-                                delimiter.fg(Color::Yellow)
+                                paint(delimiter, Color::Yellow)
                             ))
                             .with_color(Color::Yellow),
                     )
                     .with_label(
-                        Label::new(e.span())
+                        Label::new((file_name.clone(), e.span()))
                             .with_message(format!(
                                 "Must be closed before this {}",
-                                e.found()
-                                    .unwrap_or(&"end of file".to_string())
-                                    .fg(Color::Red)
+                                paint(e.found().unwrap_or(&"end of file".to_string()), Color::Red)
                             ))
                             .with_color(Color::Red),
                     ),
@@ -108,35 +291,69 @@ fn report_errors(src: &str, errors: Vec<parser::Error>) {
                         } else {
                             "Unexpected end of input"
                         },
-                        if e.expected().len() == 0 {
+                        if expected.is_empty() {
                             "something else".to_string()
                         } else {
-                            e.expected()
-                                .map(|expected| match expected {
-                                    Some(expected) => expected.to_string(),
-                                    None => "end of input".to_string(),
-                                })
-                                .collect::<Vec<_>>()
-                                .join(", ")
+                            expected.join(", ")
                         }
                     ))
                     .with_label(
-                        Label::new(e.span())
+                        Label::new((file_name.clone(), e.span()))
                             .with_message(format!(
                                 "Unexpected token {}",
-                                e.found()
-                                    .unwrap_or(&"end of file".to_string())
-                                    .fg(Color::Red)
+                                paint(e.found().unwrap_or(&"end of file".to_string()), Color::Red)
                             ))
                             .with_color(Color::Red),
                     ),
                 chumsky::error::SimpleReason::Custom(msg) => report.with_message(msg).with_label(
-                    Label::new(e.span())
-                        .with_message(format!("{}", msg.fg(Color::Red)))
+                    Label::new((file_name.clone(), e.span()))
+                        .with_message(paint(msg, Color::Red))
                         .with_color(Color::Red),
                 ),
             };
 
-            report.finish().print(Source::from(&src)).unwrap();
-        });
+            report
+                .finish()
+                .eprint((file_name.clone(), Source::from(&src)))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diagnostics_json, DiagnosticsDelta};
+
+    #[test]
+    fn unchanged_diagnostics() {
+        let delta = DiagnosticsDelta::new(&["a", "b", "a"], &["a", "b", "a"]);
+        assert!(delta.is_empty());
+        // Moving diagnostics around doesn't change them.
+        let delta = DiagnosticsDelta::new(&["a", "b", "c"], &["c", "a", "b"]);
+        assert!(delta.is_empty());
+    }
+
+    #[test]
+    fn introduced_and_fixed() {
+        let delta = DiagnosticsDelta::new(&["a", "b", "c"], &["d", "b", "e"]);
+        assert_eq!(delta.introduced, ["d", "e"]);
+        assert_eq!(delta.fixed, ["a", "c"]);
+    }
+
+    #[test]
+    fn duplicates_are_counted() {
+        let delta = DiagnosticsDelta::new(&["a", "b"], &["a", "a", "b", "a"]);
+        assert_eq!(delta.introduced, ["a", "a"]);
+        assert!(delta.fixed.is_empty());
+
+        // Only one of two identical diagnostics went away.
+        let delta = DiagnosticsDelta::new(&["a", "b", "a"], &["b", "a"]);
+        assert!(delta.introduced.is_empty());
+        assert_eq!(delta.fixed, ["a"]);
+    }
+
+    #[test]
+    fn syntax_errors_are_reported_once() {
+        let json = diagnostics_json("fn main() { let x = ; }".to_owned());
+        let diagnostics: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(diagnostics.len(), 1, "{json}");
+    }
 }
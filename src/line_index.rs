@@ -0,0 +1,152 @@
+//! Conversion between the byte offsets used in spans and line/column positions, which editors
+//! count in UTF-16 code units.
+
+use crate::{Db, SourceProgram};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    /// The byte offset at which each line starts. The first line always starts at 0.
+    line_starts: Vec<usize>,
+}
+
+/// Both are zero based. `col` is in UTF-16 code units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+#[salsa::tracked(return_ref)]
+pub fn line_index(db: &dyn Db, source: SourceProgram) -> LineIndex {
+    LineIndex::new(source.text(db))
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { line_starts }
+    }
+
+    /// Offsets past the end of `text` are clamped to the end, and offsets inside of a character
+    /// are moved to its start.
+    pub fn line_col(&self, text: &str, offset: usize) -> LineCol {
+        let mut offset = offset.min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_text = &text[self.line_starts[line]..offset];
+        LineCol {
+            line: line as u32,
+            col: line_text.encode_utf16().count() as u32,
+        }
+    }
+
+    /// Positions past the end of a line are clamped to the end of the line, before a `\r\n` or
+    /// `\n`, and positions past the last line to the end of `text`. A column in the middle of a
+    /// surrogate pair is moved behind the character.
+    pub fn offset(&self, text: &str, line_col: LineCol) -> usize {
+        let line_start = match self.line_starts.get(line_col.line as usize) {
+            Some(&start) => start,
+            None => return text.len(),
+        };
+
+        let mut col = 0;
+        let line_text = &text[line_start..];
+        for (i, c) in line_text.char_indices() {
+            let line_end = c == '\n' || line_text[i..].starts_with("\r\n");
+            if line_end || col >= line_col.col as usize {
+                return line_start + i;
+            }
+            col += c.len_utf16();
+        }
+        text.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineCol, LineIndex};
+
+    fn line_col(line: u32, col: u32) -> LineCol {
+        LineCol { line, col }
+    }
+
+    /// Checks both directions for a position that exists in `text`.
+    fn assert_roundtrip(text: &str, offset: usize, expected: LineCol) {
+        let index = LineIndex::new(text);
+        assert_eq!(index.line_col(text, offset), expected, "offset {offset}");
+        assert_eq!(index.offset(text, expected), offset, "{expected:?}");
+    }
+
+    #[test]
+    fn ascii() {
+        let text = "fn main() {\n    1;\n}\n";
+        assert_roundtrip(text, 0, line_col(0, 0));
+        assert_roundtrip(text, 3, line_col(0, 3));
+        assert_roundtrip(text, 11, line_col(0, 11));
+        assert_roundtrip(text, 12, line_col(1, 0));
+        assert_roundtrip(text, 16, line_col(1, 4));
+        assert_roundtrip(text, 21, line_col(3, 0));
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        // `ä` is two bytes and `€` three, but both are a single UTF-16 code unit.
+        let text = "let ä = \"€\";\nx";
+        assert_roundtrip(text, 4, line_col(0, 4));
+        assert_roundtrip(text, 6, line_col(0, 5));
+        assert_roundtrip(text, 10, line_col(0, 9));
+        assert_roundtrip(text, 13, line_col(0, 10));
+        assert_roundtrip(text, 16, line_col(1, 0));
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        // `🦀` is four bytes and two UTF-16 code units.
+        let text = "\"🦀\" x";
+        assert_roundtrip(text, 1, line_col(0, 1));
+        assert_roundtrip(text, 5, line_col(0, 3));
+        assert_roundtrip(text, 7, line_col(0, 5));
+
+        let index = LineIndex::new(text);
+        // Between the two halves of the pair.
+        assert_eq!(index.offset(text, line_col(0, 2)), 5);
+        // Inside of the four bytes.
+        assert_eq!(index.line_col(text, 3), line_col(0, 1));
+    }
+
+    #[test]
+    fn crlf() {
+        let text = "a;\r\nbc;\r\n";
+        assert_roundtrip(text, 2, line_col(0, 2));
+        assert_roundtrip(text, 4, line_col(1, 0));
+        assert_roundtrip(text, 7, line_col(1, 3));
+        assert_roundtrip(text, 9, line_col(2, 0));
+
+        let index = LineIndex::new(text);
+        // Past the end of the line stops before the `\r`.
+        assert_eq!(index.offset(text, line_col(0, 10)), 2);
+        assert_eq!(index.offset(text, line_col(1, 10)), 7);
+    }
+
+    #[test]
+    fn past_the_end() {
+        let text = "ab\ncd";
+        let index = LineIndex::new(text);
+        assert_eq!(index.offset(text, line_col(0, 5)), 2);
+        assert_eq!(index.offset(text, line_col(1, 5)), 5);
+        assert_eq!(index.offset(text, line_col(7, 0)), 5);
+        assert_eq!(index.line_col(text, 100), line_col(1, 2));
+    }
+
+    #[test]
+    fn empty() {
+        let index = LineIndex::new("");
+        assert_eq!(index.line_col("", 0), line_col(0, 0));
+        assert_eq!(index.offset("", line_col(0, 0)), 0);
+        assert_eq!(index.offset("", line_col(1, 3)), 0);
+    }
+}
@@ -0,0 +1,105 @@
+//! Checks that embedders add to the compiler, for rules that only matter to their project. They
+//! run on every file without syntax errors and report through [`Diagnostics`] like the parser.
+
+use chumsky::error::Simple;
+
+use crate::{
+    ast::File,
+    parser::{parse, Error, Span},
+    resolve::{resolve, Resolutions},
+    Db, Diagnostics, SourceProgram,
+};
+
+pub trait LintPass: Send + Sync {
+    fn check_file(&self, cx: &LintContext<'_>);
+}
+
+pub struct LintContext<'a> {
+    db: &'a dyn Db,
+    pub file: &'a File,
+    pub resolutions: &'a Resolutions,
+}
+
+impl LintContext<'_> {
+    pub fn report(&self, span: Span, message: impl ToString) {
+        Diagnostics::push(self.db, Error(Simple::custom(span, message)));
+    }
+}
+
+#[salsa::tracked]
+pub fn lint(db: &dyn Db, source: SourceProgram) {
+    let passes = db.lint_passes();
+    if passes.is_empty() {
+        return;
+    }
+    let file = match parse(db, source) {
+        Some(file) => file,
+        None => return,
+    };
+    let cx = LintContext {
+        db,
+        file: &file,
+        resolutions: resolve(db, source),
+    };
+    for pass in passes {
+        pass.check_file(&cx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{lint, LintContext, LintPass};
+    use crate::{ast::Item, resolve::DefKind, Database, Diagnostics, SourceProgram};
+
+    /// Functions have to be named in snake case.
+    struct SnakeCase;
+
+    impl LintPass for SnakeCase {
+        fn check_file(&self, cx: &LintContext<'_>) {
+            for (_, def) in cx.resolutions.defs() {
+                if def.kind == DefKind::Fn && def.name.chars().any(|c| c.is_ascii_uppercase()) {
+                    cx.report(
+                        def.name_span.clone(),
+                        format!("function `{}` should have a snake case name", def.name),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Functions must not be empty.
+    struct EmptyFn;
+
+    impl LintPass for EmptyFn {
+        fn check_file(&self, cx: &LintContext<'_>) {
+            for item in &cx.file.items {
+                if let Item::FnDecl(fn_decl) = item {
+                    if fn_decl.body.is_empty() {
+                        cx.report(fn_decl.span.clone(), "empty function");
+                    }
+                }
+            }
+        }
+    }
+
+    fn lint_src(src: &str) -> Vec<crate::parser::Error> {
+        let db = Database::with_lint_passes(vec![Arc::new(SnakeCase), Arc::new(EmptyFn)]);
+        let source = SourceProgram::new(&db, src.to_owned(), "uwu.ub".into());
+        lint(&db, source);
+        lint::accumulated::<Diagnostics>(&db, source)
+    }
+
+    #[test]
+    fn passes_run_in_order() {
+        let errors = lint_src("fn doStuff() {}\nfn fine() { doStuff(); }\nfn Empty() {}");
+        insta::assert_debug_snapshot!(errors);
+    }
+
+    #[test]
+    fn files_with_syntax_errors_are_skipped() {
+        // The parse errors are reported by the parser, not again by `lint`.
+        assert_eq!(lint_src("fn doStuff() {").len(), 1);
+    }
+}
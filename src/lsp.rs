@@ -0,0 +1,1524 @@
+//! The language server behind the `ub-lsp` binary. It speaks LSP over stdio and keeps every open
+//! document as a [`SourceProgram`] input, so all analysis goes through the same queries as the
+//! command line driver.
+//!
+//! Set `UB_LOG_QUERIES` to log the queries that every edit causes to be executed or validated to
+//! standard error.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env,
+    error::Error,
+    mem,
+    panic::AssertUnwindSafe,
+    path::PathBuf,
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{
+        CodeActionRequest, Completion, DocumentSymbolRequest, Formatting, GotoDefinition,
+        HoverRequest, OnTypeFormatting, RangeFormatting, References, Rename,
+        SemanticTokensFullRequest, SignatureHelpRequest,
+    },
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CodeActionResponse, CompletionItem, CompletionItemKind,
+    CompletionOptions, CompletionParams, CompletionResponse, Diagnostic, DiagnosticSeverity,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentFormattingParams, DocumentOnTypeFormattingOptions, DocumentOnTypeFormattingParams,
+    DocumentRangeFormattingParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    Documentation, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, InitializeParams, Location, MarkupContent, MarkupKind, OneOf,
+    ParameterInformation, ParameterLabel, Position, PublishDiagnosticsParams, Range,
+    ReferenceParams, RenameParams, SemanticToken, SemanticTokenModifier, SemanticTokenType,
+    SemanticTokens, SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions,
+    SemanticTokensParams, SemanticTokensResult, SemanticTokensServerCapabilities,
+    ServerCapabilities, SignatureHelp, SignatureHelpOptions, SignatureHelpParams,
+    SignatureInformation, SymbolKind, TextDocumentPositionParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url, WorkspaceEdit,
+};
+use salsa::{Cancelled, ParallelDatabase, Snapshot};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    ast::{
+        nodes::{find_nodes, nodes, Node, NodeKind},
+        File, Item, TyKind,
+    },
+    lexer::{self, Token},
+    line_index::{line_index, LineCol, LineIndex},
+    parser::{self, Span},
+    pretty::{
+        fn_signature, pretty_print_ast_with_comments, pretty_print_item_with_comments,
+        pretty_print_ty, struct_signature,
+    },
+    resolve::{references, resolve, Def, DefKind, Resolutions},
+    validate::validate,
+    Database, Diagnostics, DiagnosticsDelta, QueryEvent, SourceProgram,
+};
+
+/// How long the client has to be quiet before diagnostics are recomputed, so that they aren't
+/// computed for every keystroke.
+const DIAGNOSTICS_DELAY: Duration = Duration::from_millis(200);
+
+const KEYWORDS: &[&str] = &[
+    "struct", "fn", "if", "else", "while", "loop", "ptr", "let", "return", "break", "continue",
+    "true", "false",
+];
+
+/// Indexed by [`token_type`].
+const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::STRUCT,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::VARIABLE,
+];
+/// The bit of the declaration modifier. It's the only modifier.
+const DECLARATION_MODIFIER: u32 = 1;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+pub fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = serde_json::to_value(capabilities())?;
+    let params = connection.initialize(capabilities)?;
+    let _params: InitializeParams = serde_json::from_value(params)?;
+
+    let mut db = Database::default();
+    if env::var_os("UB_LOG_QUERIES").is_some() {
+        // Clients usually show the standard error of servers in a log.
+        db.set_query_log(|event, query| match event {
+            QueryEvent::Executed => eprintln!("executed {query}"),
+            QueryEvent::Validated => eprintln!("validated {query}"),
+        });
+    }
+
+    let mut server = Server {
+        connection,
+        db,
+        documents: HashMap::new(),
+        stale_diagnostics: Arc::default(),
+        published_diagnostics: Arc::default(),
+    };
+    server.main_loop()?;
+
+    // The connection has to be dropped first, otherwise the writer thread never finishes.
+    drop(server);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(
+            TextDocumentSyncKind::INCREMENTAL,
+        )),
+        definition_provider: Some(OneOf::Left(true)),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+            ..SignatureHelpOptions::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: "}".to_string(),
+            more_trigger_character: None,
+        }),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: SemanticTokensLegend {
+                    token_types: TOKEN_TYPES.to_vec(),
+                    token_modifiers: vec![SemanticTokenModifier::DECLARATION],
+                },
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..SemanticTokensOptions::default()
+            },
+        )),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    }
+}
+
+struct Server {
+    connection: Connection,
+    db: Database,
+    /// The open documents. Their text is owned by the client, not the file system.
+    documents: HashMap<Url, SourceProgram>,
+    /// Documents that changed since their diagnostics were last published. Shared with the
+    /// thread that publishes them, and ordered so that they are always published in the same order.
+    stale_diagnostics: Arc<Mutex<BTreeSet<Url>>>,
+    /// The diagnostics that the client was last sent for each open document, so that unchanged
+    /// ones aren't sent again after every edit. The source tells whether the document was closed
+    /// or reopened while its diagnostics were computed, in which case they aren't sent.
+    published_diagnostics: Arc<Mutex<PublishedDiagnostics>>,
+}
+
+type PublishedDiagnostics = HashMap<Url, (SourceProgram, Vec<Diagnostic>)>;
+
+impl Server {
+    fn main_loop(&mut self) -> Result<()> {
+        loop {
+            let message = if self.stale_diagnostics.lock().unwrap().is_empty() {
+                match self.connection.receiver.recv() {
+                    Ok(message) => message,
+                    Err(_) => return Ok(()),
+                }
+            } else {
+                match self.connection.receiver.recv_timeout(DIAGNOSTICS_DELAY) {
+                    Ok(message) => message,
+                    Err(err) if err.is_timeout() => {
+                        self.publish_diagnostics();
+                        continue;
+                    }
+                    Err(_) => return Ok(()),
+                }
+            };
+
+            match message {
+                Message::Request(req) => {
+                    if self.connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    self.handle_request(req);
+                }
+                Message::Notification(notification) => self.handle_notification(notification),
+                Message::Response(_) => {}
+            }
+        }
+    }
+
+    fn handle_request(&mut self, req: Request) {
+        RequestDispatcher {
+            server: self,
+            req: Some(req),
+        }
+        .on::<GotoDefinition>(Analysis::goto_definition)
+        .on::<HoverRequest>(Analysis::hover)
+        .on::<Completion>(Analysis::completion)
+        .on::<SignatureHelpRequest>(Analysis::signature_help)
+        .on::<DocumentSymbolRequest>(Analysis::document_symbols)
+        .on::<SemanticTokensFullRequest>(Analysis::semantic_tokens)
+        .on::<References>(Analysis::references)
+        .try_on::<Rename>(Analysis::rename)
+        .on::<CodeActionRequest>(Analysis::code_actions)
+        .try_on::<Formatting>(Analysis::formatting)
+        .try_on::<RangeFormatting>(Analysis::range_formatting)
+        .try_on::<OnTypeFormatting>(Analysis::on_type_formatting)
+        .finish();
+    }
+
+    fn handle_notification(&mut self, notification: Notification) {
+        NotificationDispatcher {
+            server: self,
+            notification: Some(notification),
+        }
+        .on::<DidOpenTextDocument>(Server::did_open)
+        .on::<DidChangeTextDocument>(Server::did_change)
+        .on::<DidCloseTextDocument>(Server::did_close);
+    }
+
+    fn send(&self, message: Message) {
+        self.connection
+            .sender
+            .send(message)
+            .expect("the connection to the client was closed");
+    }
+
+    fn did_open(&mut self, params: DidOpenTextDocumentParams) {
+        let document = params.text_document;
+        self.set_text(document.uri, document.text);
+    }
+
+    /// The changes are applied in order. Each one is relative to the text after the previous one.
+    fn did_change(&mut self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let mut text = match self.documents.get(&uri) {
+            Some(source) => source.text(&self.db).clone(),
+            None => return,
+        };
+        for change in params.content_changes {
+            match change.range {
+                Some(range) => {
+                    let index = LineIndex::new(&text);
+                    let start = index.offset(&text, line_col(range.start));
+                    let end = index.offset(&text, line_col(range.end));
+                    text.replace_range(start..end.max(start), &change.text);
+                }
+                None => text = change.text,
+            }
+        }
+        self.set_text(uri, text);
+    }
+
+    fn did_close(&mut self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.remove(&uri);
+        self.stale_diagnostics.lock().unwrap().remove(&uri);
+        self.published_diagnostics.lock().unwrap().remove(&uri);
+        // The diagnostics of closed documents are cleared since they can't be kept up to date.
+        self.send(diagnostics_notification(uri, Vec::new()).into());
+    }
+
+    fn set_text(&mut self, uri: Url, text: String) {
+        match self.documents.get(&uri) {
+            Some(&source) => {
+                source.set_text(&mut self.db).to(text);
+            }
+            None => {
+                let source = SourceProgram::new(&self.db, text, file_name(&uri));
+                self.documents.insert(uri.clone(), source);
+                self.published_diagnostics
+                    .lock()
+                    .unwrap()
+                    .insert(uri.clone(), (source, Vec::new()));
+            }
+        }
+        self.stale_diagnostics.lock().unwrap().insert(uri);
+    }
+
+    /// The diagnostics are computed on a worker thread. If an edit cancels them, the documents
+    /// that weren't published yet are marked as stale again.
+    fn publish_diagnostics(&mut self) {
+        let uris = mem::take(&mut *self.stale_diagnostics.lock().unwrap())
+            .into_iter()
+            .collect::<Vec<_>>();
+        let analysis = self.analysis();
+        let stale_diagnostics = Arc::clone(&self.stale_diagnostics);
+        let published_diagnostics = Arc::clone(&self.published_diagnostics);
+        let sender = self.connection.sender.clone();
+        thread::spawn(move || {
+            for (i, uri) in uris.iter().enumerate() {
+                let source = match analysis.documents.get(uri) {
+                    Some(&source) => source,
+                    None => continue,
+                };
+                match Cancelled::catch(AssertUnwindSafe(|| analysis.diagnostics(source))) {
+                    Ok(diagnostics) => {
+                        // Checked while holding the lock, which `did_close` takes as well.
+                        let mut published = published_diagnostics.lock().unwrap();
+                        let old = match published.get_mut(uri) {
+                            Some((open, old)) if *open == source => old,
+                            _ => continue,
+                        };
+                        if DiagnosticsDelta::new(old, &diagnostics).is_empty() {
+                            continue;
+                        }
+                        *old = diagnostics.clone();
+                        sender
+                            .send(diagnostics_notification(uri.clone(), diagnostics).into())
+                            .expect("the connection to the client was closed");
+                    }
+                    Err(_) => {
+                        // The edit that cancelled the analysis waits for it to be dropped, so
+                        // the main loop sees these before it sees the edit.
+                        stale_diagnostics
+                            .lock()
+                            .unwrap()
+                            .extend(uris[i..].iter().cloned());
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    fn analysis(&self) -> Analysis {
+        Analysis {
+            db: self.db.snapshot(),
+            documents: self.documents.clone(),
+        }
+    }
+}
+
+/// A read-only view of the open documents that requests are answered from on worker threads.
+/// Any edit cancels the queries that are running on it.
+struct Analysis {
+    db: Snapshot<Database>,
+    documents: HashMap<Url, SourceProgram>,
+}
+
+impl Analysis {
+    fn goto_definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let (source, offset) = self.position_offset(&params.text_document_position_params)?;
+        let resolutions = resolve(&*self.db, source);
+        let def = resolutions.def(resolutions.def_at(offset)?);
+        let location = Location::new(
+            params.text_document_position_params.text_document.uri,
+            self.range(source, def.name_span.clone()),
+        );
+        Some(GotoDefinitionResponse::Scalar(location))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let (source, offset) = self.position_offset(&params.text_document_position_params)?;
+        let resolutions = resolve(&*self.db, source);
+        let (span, def) = resolutions.name_at(offset)?;
+        let file = parser::parse(&*self.db, source)?;
+        let (signature, doc) = describe(&file, resolutions.def(def));
+
+        let mut value = format!("```ub\n{signature}\n```");
+        if let Some(doc) = doc {
+            value.push_str("\n\n---\n\n");
+            value.push_str(doc);
+        }
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(self.range(source, span)),
+        })
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let (source, offset) = self.position_offset(&params.text_document_position)?;
+        let text = source.text(&*self.db);
+        let resolutions = resolve(&*self.db, source);
+
+        // The word that is being typed is replaced by the completion.
+        let before_word =
+            text[..offset].trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+        let items = match before_word.strip_suffix('.') {
+            Some(receiver) => field_completions(resolutions, receiver.len()),
+            None => scope_completions(resolutions, offset),
+        };
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn signature_help(&self, params: SignatureHelpParams) -> Option<SignatureHelp> {
+        let (source, offset) = self.position_offset(&params.text_document_position_params)?;
+        let text = source.text(&*self.db);
+        let (callee, active_parameter) = enclosing_call(&text[..offset])?;
+
+        // Calls that are still being typed don't parse, so the callee is often not resolved.
+        let resolutions = resolve(&*self.db, source);
+        let def = resolutions
+            .def_at(callee.end)
+            .filter(|&def| resolutions.def(def).kind == DefKind::Fn)
+            .or_else(|| {
+                resolutions
+                    .visible_at(offset)
+                    .filter(|(_, def)| def.kind == DefKind::Fn && def.name == text[callee.clone()])
+                    .map(|(id, _)| id)
+                    .last()
+            })?;
+        let name_span = &resolutions.def(def).name_span;
+        let file = parser::parse(&*self.db, source)?;
+        let fn_decl =
+            find_nodes(&file, NodeKind::FnDecl)
+                .into_iter()
+                .find_map(|node| match node {
+                    Node::FnDecl(fn_decl) if fn_decl.name_span == *name_span => Some(fn_decl),
+                    _ => None,
+                })?;
+
+        let label = fn_signature(fn_decl);
+        let mut param_start = label.find('(')? + 1;
+        let mut parameters = Vec::new();
+        for param in &fn_decl.params {
+            let param_label = format!("{}: {}", param.name, pretty_print_ty(&param.ty));
+            let start = param_start + label[param_start..].find(&param_label)?;
+            let end = start + param_label.len();
+            let utf16_len = |text: &str| text.encode_utf16().count() as u32;
+            parameters.push(ParameterInformation {
+                label: ParameterLabel::LabelOffsets([
+                    utf16_len(&label[..start]),
+                    utf16_len(&label[..end]),
+                ]),
+                documentation: None,
+            });
+            param_start = end;
+        }
+
+        let signature = SignatureInformation {
+            label,
+            documentation: fn_decl.doc.clone().map(|doc| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: doc,
+                })
+            }),
+            parameters: Some(parameters),
+            active_parameter: None,
+        };
+        Some(SignatureHelp {
+            signatures: vec![signature],
+            active_signature: Some(0),
+            active_parameter: Some(active_parameter),
+        })
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let source = *self.documents.get(&params.text_document.uri)?;
+        let file = parser::parse(&*self.db, source)?;
+
+        let symbols = file
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::FnDecl(fn_decl) => self.symbol(
+                    source,
+                    &fn_decl.name,
+                    SymbolKind::FUNCTION,
+                    Some(fn_signature(fn_decl)),
+                    (fn_decl.span.clone(), fn_decl.name_span.clone()),
+                    None,
+                ),
+                Item::StructDecl(struct_decl) => {
+                    let fields = struct_decl
+                        .fields
+                        .iter()
+                        .map(|field| {
+                            self.symbol(
+                                source,
+                                &field.name,
+                                SymbolKind::FIELD,
+                                Some(pretty_print_ty(&field.ty)),
+                                (field.span.clone(), field.name_span.clone()),
+                                None,
+                            )
+                        })
+                        .collect();
+                    self.symbol(
+                        source,
+                        &struct_decl.name,
+                        SymbolKind::STRUCT,
+                        None,
+                        (struct_decl.span.clone(), struct_decl.name_span.clone()),
+                        Some(fields),
+                    )
+                }
+            })
+            .collect();
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    /// `spans` are the whole declaration and its name.
+    fn symbol(
+        &self,
+        source: SourceProgram,
+        name: &str,
+        kind: SymbolKind,
+        detail: Option<String>,
+        spans: (Span, Span),
+        children: Option<Vec<DocumentSymbol>>,
+    ) -> DocumentSymbol {
+        #[allow(deprecated)] // `deprecated` has to be set even though it's deprecated.
+        DocumentSymbol {
+            name: name.to_owned(),
+            detail,
+            kind,
+            tags: None,
+            deprecated: None,
+            range: self.range(source, spans.0),
+            selection_range: self.range(source, spans.1),
+            children,
+        }
+    }
+
+    /// Classifies every name that could be resolved.
+    fn semantic_tokens(&self, params: SemanticTokensParams) -> Option<SemanticTokensResult> {
+        let source = *self.documents.get(&params.text_document.uri)?;
+        let text = source.text(&*self.db);
+        let index = line_index(&*self.db, source);
+        let resolutions = resolve(&*self.db, source);
+
+        let names = resolutions.names();
+
+        // Tokens are encoded relative to the start of the previous token.
+        let mut data = Vec::with_capacity(names.len());
+        let mut previous = LineCol { line: 0, col: 0 };
+        for (span, def, is_declaration) in names {
+            let start = index.line_col(text, span.start);
+            let end = index.line_col(text, span.end);
+            data.push(SemanticToken {
+                delta_line: start.line - previous.line,
+                delta_start: if start.line == previous.line {
+                    start.col - previous.col
+                } else {
+                    start.col
+                },
+                length: end.col - start.col,
+                token_type: token_type(resolutions.def(def).kind),
+                token_modifiers_bitset: if is_declaration {
+                    DECLARATION_MODIFIER
+                } else {
+                    0
+                },
+            });
+            previous = start;
+        }
+
+        Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        }))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let (source, offset) = self.position_offset(&params.text_document_position)?;
+        let resolutions = resolve(&*self.db, source);
+        let def = resolutions.def_at(offset)?;
+
+        let declaration = params
+            .context
+            .include_declaration
+            .then(|| &resolutions.def(def).name_span);
+        let uri = params.text_document_position.text_document.uri;
+        let locations = declaration
+            .into_iter()
+            .chain(references(&*self.db, source).of(def))
+            .map(|span| Location::new(uri.clone(), self.range(source, span.clone())))
+            .collect();
+        Some(locations)
+    }
+
+    /// Renames a definition and all names that refer to it. The new name is rejected if it
+    /// isn't an identifier or would change what other names refer to.
+    fn rename(&self, params: RenameParams) -> std::result::Result<Option<WorkspaceEdit>, String> {
+        let (source, offset) = match self.position_offset(&params.text_document_position) {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        let resolutions = resolve(&*self.db, source);
+        let def = match resolutions.def_at(offset) {
+            Some(def) => def,
+            None => return Ok(None),
+        };
+
+        let new_name = params.new_name;
+        if KEYWORDS.contains(&new_name.as_str()) {
+            return Err(format!("`{new_name}` is a keyword"));
+        }
+        if !matches!(
+            lexer::lex(&new_name).collect::<Vec<_>>()[..],
+            [Token::Ident(_)]
+        ) {
+            return Err(format!("`{new_name}` is not a valid identifier"));
+        }
+        if new_name == "bool" && resolutions.def(def).kind == DefKind::Struct {
+            return Err("`bool` is a primitive type".to_string());
+        }
+        if let Some(other) = resolutions.conflict(def, &new_name) {
+            let other = resolutions.def(other);
+            let line = self.range(source, other.name_span.clone()).start.line + 1;
+            return Err(format!("`{new_name}` is already defined on line {line}"));
+        }
+
+        let edits = references(&*self.db, source)
+            .of(def)
+            .iter()
+            .chain([&resolutions.def(def).name_span])
+            .map(|span| TextEdit::new(self.range(source, span.clone()), new_name.clone()))
+            .collect();
+        let uri = params.text_document_position.text_document.uri;
+        Ok(Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, edits)])),
+            ..WorkspaceEdit::default()
+        }))
+    }
+
+    /// Quick fixes for the errors in the requested range.
+    fn code_actions(&self, params: CodeActionParams) -> Option<CodeActionResponse> {
+        let uri = params.text_document.uri;
+        let source = *self.documents.get(&uri)?;
+
+        parser::parse(&*self.db, source);
+        let actions = parser::parse::accumulated::<Diagnostics>(&*self.db, source)
+            .into_iter()
+            .filter_map(|err| {
+                let diagnostic = self.diagnostic(source, &err);
+                let Range { start, end } = diagnostic.range;
+                if end < params.range.start || params.range.end < start {
+                    return None;
+                }
+                let suggestion = err.suggestion()?;
+                let edit =
+                    TextEdit::new(self.range(source, suggestion.span), suggestion.replacement);
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: suggestion.message,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                        ..WorkspaceEdit::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..CodeAction::default()
+                }))
+            })
+            .collect();
+        Some(actions)
+    }
+
+    fn formatting(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> std::result::Result<Option<Vec<TextEdit>>, String> {
+        let source = match self.documents.get(&params.text_document.uri) {
+            Some(&source) => source,
+            None => return Ok(None),
+        };
+        let file = match self.formattable_file(source)? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let text = source.text(&*self.db);
+        let formatted = pretty_print_ast_with_comments(&file, text);
+        if formatted == *text {
+            return Ok(Some(Vec::new()));
+        }
+        Ok(Some(vec![TextEdit::new(
+            self.range(source, 0..text.len()),
+            formatted,
+        )]))
+    }
+
+    fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> std::result::Result<Option<Vec<TextEdit>>, String> {
+        let source = match self.documents.get(&params.text_document.uri) {
+            Some(&source) => source,
+            None => return Ok(None),
+        };
+        let text = source.text(&*self.db);
+        let index = line_index(&*self.db, source);
+        let start = index.offset(text, line_col(params.range.start));
+        let end = index.offset(text, line_col(params.range.end));
+        self.format_items(source, start..end)
+    }
+
+    /// Formats the item that was just closed.
+    fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> std::result::Result<Option<Vec<TextEdit>>, String> {
+        match self.position_offset(&params.text_document_position) {
+            Some((source, offset)) => self.format_items(source, offset..offset),
+            None => Ok(None),
+        }
+    }
+
+    /// Formats the top level items that overlap `span`, leaving the rest of the file alone.
+    fn format_items(
+        &self,
+        source: SourceProgram,
+        span: Span,
+    ) -> std::result::Result<Option<Vec<TextEdit>>, String> {
+        let file = match self.formattable_file(source)? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let text = source.text(&*self.db);
+        let mut edits = Vec::new();
+        for item in &file.items {
+            let item_span = item.span();
+            if item_span.end < span.start || span.end < item_span.start {
+                continue;
+            }
+            let formatted = pretty_print_item_with_comments(item, text);
+            if formatted != text[item_span.clone()] {
+                edits.push(TextEdit::new(
+                    self.range(source, item_span.clone()),
+                    formatted,
+                ));
+            }
+        }
+        Ok(Some(edits))
+    }
+
+    /// Files with syntax errors aren't formatted, since the parser drops the code that it can't
+    /// make sense of.
+    fn formattable_file(&self, source: SourceProgram) -> std::result::Result<Option<File>, String> {
+        let file = match parser::parse(&*self.db, source) {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        if parser::parse::accumulated::<Diagnostics>(&*self.db, source).is_empty() {
+            Ok(Some(file))
+        } else {
+            Err("files with syntax errors can't be formatted".to_string())
+        }
+    }
+
+    fn diagnostics(&self, source: SourceProgram) -> Vec<Diagnostic> {
+        // Includes the syntax errors, since `validate` parses the file.
+        validate(&*self.db, source);
+        validate::accumulated::<Diagnostics>(&*self.db, source)
+            .iter()
+            .map(|err| self.diagnostic(source, err))
+            .collect()
+    }
+
+    fn diagnostic(&self, source: SourceProgram, err: &parser::Error) -> Diagnostic {
+        Diagnostic {
+            range: self.range(source, err.span()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("ub".to_string()),
+            message: err.message(),
+            ..Diagnostic::default()
+        }
+    }
+
+    /// The document and the byte offset in it that a request is about.
+    fn position_offset(
+        &self,
+        params: &TextDocumentPositionParams,
+    ) -> Option<(SourceProgram, usize)> {
+        let source = *self.documents.get(&params.text_document.uri)?;
+        let text = source.text(&*self.db);
+        let offset = line_index(&*self.db, source).offset(text, line_col(params.position));
+        Some((source, offset))
+    }
+
+    fn range(&self, source: SourceProgram, span: Span) -> Range {
+        let text = source.text(&*self.db);
+        let index = line_index(&*self.db, source);
+        Range::new(
+            position(index.line_col(text, span.start)),
+            position(index.line_col(text, span.end)),
+        )
+    }
+}
+
+/// The index into [`TOKEN_TYPES`].
+fn token_type(kind: DefKind) -> u32 {
+    match kind {
+        DefKind::Fn => 0,
+        DefKind::Struct => 1,
+        DefKind::Field => 2,
+        DefKind::Param => 3,
+        DefKind::Local => 4,
+    }
+}
+
+/// The callee of the call whose argument list is unclosed at the end of `text`, and the index of
+/// the argument that `text` ends in. Only calls of plain names are found.
+fn enclosing_call(text: &str) -> Option<(Span, u32)> {
+    let tokens = lexer::lex(text).spanned().collect::<Vec<_>>();
+    let mut depth = 0;
+    let mut commas = 0;
+    for (i, (token, _)) in tokens.iter().enumerate().rev() {
+        match token {
+            Token::ParenC | Token::BracketC | Token::BraceC => depth += 1,
+            Token::ParenO | Token::BracketO | Token::BraceO if depth > 0 => depth -= 1,
+            Token::ParenO => {
+                return match &tokens[..i] {
+                    [.., (Token::Ident(_), span)] => Some((span.clone(), commas)),
+                    _ => None,
+                };
+            }
+            // The end of `text` is in an array or a block, not directly in an argument list.
+            Token::BracketO | Token::BraceO => return None,
+            Token::Comma if depth == 0 => commas += 1,
+            Token::Semi if depth == 0 => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The fields of the struct that the name ending at `receiver_end` has as its declared type.
+fn field_completions(resolutions: &Resolutions, receiver_end: usize) -> Vec<CompletionItem> {
+    let receiver = match resolutions.def_at(receiver_end) {
+        Some(def) => resolutions.def(def),
+        None => return Vec::new(),
+    };
+    let struct_id = match receiver.ty.as_ref().map(|ty| &ty.kind) {
+        Some(TyKind::Name(name)) => resolutions.struct_named(name),
+        _ => None,
+    };
+
+    struct_id
+        .into_iter()
+        .flat_map(|struct_id| resolutions.fields(struct_id))
+        .map(|(_, field)| CompletionItem {
+            label: field.name.clone(),
+            kind: Some(CompletionItemKind::FIELD),
+            detail: field.ty.as_ref().map(pretty_print_ty),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Keywords and everything that can be named at `offset`.
+fn scope_completions(resolutions: &Resolutions, offset: usize) -> Vec<CompletionItem> {
+    // Later definitions shadow earlier ones. Structs have their own namespace.
+    let mut visible = BTreeMap::new();
+    for (_, def) in resolutions.visible_at(offset) {
+        visible.insert((def.name.as_str(), def.kind == DefKind::Struct), def);
+    }
+
+    let defs = visible.into_values().map(|def| {
+        let kind = match def.kind {
+            DefKind::Fn => CompletionItemKind::FUNCTION,
+            DefKind::Struct => CompletionItemKind::STRUCT,
+            DefKind::Field => CompletionItemKind::FIELD,
+            DefKind::Param | DefKind::Local => CompletionItemKind::VARIABLE,
+        };
+        CompletionItem {
+            label: def.name.clone(),
+            kind: Some(kind),
+            detail: def.ty.as_ref().map(pretty_print_ty),
+            ..CompletionItem::default()
+        }
+    });
+    let keywords = KEYWORDS.iter().map(|keyword| CompletionItem {
+        label: keyword.to_string(),
+        kind: Some(CompletionItemKind::KEYWORD),
+        ..CompletionItem::default()
+    });
+    defs.chain(keywords).collect()
+}
+
+/// The declaration of a definition as it would be written in code, and its documentation.
+/// There is no type inference yet, so locals only show their declared type.
+fn describe<'a>(file: &'a File, def: &Def) -> (String, Option<&'a str>) {
+    // Functions can also be declared in blocks, not only as items of the file.
+    for node in nodes(file) {
+        match node {
+            Node::FnDecl(fn_decl) if fn_decl.name_span == def.name_span => {
+                return (fn_signature(fn_decl), fn_decl.doc.as_deref());
+            }
+            Node::StructDecl(struct_decl) if struct_decl.name_span == def.name_span => {
+                return (struct_signature(struct_decl), struct_decl.doc.as_deref());
+            }
+            Node::NameTyPair(pair) if pair.name_span == def.name_span => {
+                let signature = format!("{}: {}", pair.name, pretty_print_ty(&pair.ty));
+                return (signature, pair.doc.as_deref());
+            }
+            _ => {}
+        }
+    }
+
+    let ty = def.ty.as_ref().map(pretty_print_ty);
+    let signature = match (def.kind, ty) {
+        (DefKind::Local, Some(ty)) => format!("let {}: {ty}", def.name),
+        (DefKind::Local, None) => format!("let {}", def.name),
+        (_, Some(ty)) => format!("{}: {ty}", def.name),
+        (_, None) => def.name.clone(),
+    };
+    (signature, None)
+}
+
+fn diagnostics_notification(uri: Url, diagnostics: Vec<Diagnostic>) -> Notification {
+    let params = PublishDiagnosticsParams {
+        uri,
+        diagnostics,
+        version: None,
+    };
+    Notification::new(PublishDiagnostics::METHOD.to_string(), params)
+}
+
+fn position(line_col: LineCol) -> Position {
+    Position::new(line_col.line, line_col.col)
+}
+
+fn line_col(position: Position) -> LineCol {
+    LineCol {
+        line: position.line,
+        col: position.character,
+    }
+}
+
+/// The file name used in diagnostics. Documents that aren't files are named after their URI.
+fn file_name(uri: &Url) -> PathBuf {
+    uri.to_file_path()
+        .unwrap_or_else(|()| PathBuf::from(uri.as_str()))
+}
+
+fn cast<T: DeserializeOwned>(params: serde_json::Value) -> serde_json::Result<T> {
+    serde_json::from_value(params)
+}
+
+/// Routes a request to the first handler registered for its method. The handler runs on its own
+/// thread, which sends the response.
+struct RequestDispatcher<'a> {
+    server: &'a mut Server,
+    /// `None` once the request has been handled.
+    req: Option<Request>,
+}
+
+impl RequestDispatcher<'_> {
+    fn on<R>(&mut self, handler: fn(&Analysis, R::Params) -> R::Result) -> &mut Self
+    where
+        R: lsp_types::request::Request,
+        R::Params: Send + 'static,
+        R::Result: 'static,
+    {
+        self.dispatch::<R>(move |analysis, params| Ok(handler(analysis, params)))
+    }
+
+    /// Like [`RequestDispatcher::on`], but the handler can reject the request with a message for
+    /// the user.
+    fn try_on<R>(
+        &mut self,
+        handler: fn(&Analysis, R::Params) -> std::result::Result<R::Result, String>,
+    ) -> &mut Self
+    where
+        R: lsp_types::request::Request,
+        R::Params: Send + 'static,
+        R::Result: 'static,
+    {
+        self.dispatch::<R>(handler)
+    }
+
+    fn dispatch<R>(
+        &mut self,
+        handler: impl FnOnce(&Analysis, R::Params) -> std::result::Result<R::Result, String>
+            + Send
+            + 'static,
+    ) -> &mut Self
+    where
+        R: lsp_types::request::Request,
+        R::Params: Send + 'static,
+        R::Result: 'static,
+    {
+        let req = match self.req.take() {
+            Some(req) if req.method == R::METHOD => req,
+            req => {
+                self.req = req;
+                return self;
+            }
+        };
+
+        let params = match cast::<R::Params>(req.params) {
+            Ok(params) => params,
+            Err(err) => {
+                let response =
+                    Response::new_err(req.id, ErrorCode::InvalidParams as i32, err.to_string());
+                self.server.send(response.into());
+                return self;
+            }
+        };
+
+        let analysis = self.server.analysis();
+        let sender = self.server.connection.sender.clone();
+        thread::spawn(move || {
+            let response = match Cancelled::catch(AssertUnwindSafe(|| handler(&analysis, params))) {
+                Ok(Ok(result)) => Response::new_ok(req.id, result),
+                Ok(Err(message)) => {
+                    Response::new_err(req.id, ErrorCode::InvalidParams as i32, message)
+                }
+                // The client asks again if it still needs the result for the new text.
+                Err(_) => Response::new_err(
+                    req.id,
+                    ErrorCode::ContentModified as i32,
+                    "the document was changed".to_string(),
+                ),
+            };
+            sender
+                .send(response.into())
+                .expect("the connection to the client was closed");
+        });
+        self
+    }
+
+    fn finish(&mut self) {
+        if let Some(req) = self.req.take() {
+            let response = Response::new_err(
+                req.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unknown request `{}`", req.method),
+            );
+            self.server.send(response.into());
+        }
+    }
+}
+
+/// Like [`RequestDispatcher`], but unknown notifications are ignored as the protocol requires.
+struct NotificationDispatcher<'a> {
+    server: &'a mut Server,
+    notification: Option<Notification>,
+}
+
+impl NotificationDispatcher<'_> {
+    fn on<N>(&mut self, handler: fn(&mut Server, N::Params)) -> &mut Self
+    where
+        N: lsp_types::notification::Notification,
+    {
+        let notification = match self.notification.take() {
+            Some(notification) if notification.method == N::METHOD => notification,
+            notification => {
+                self.notification = notification;
+                return self;
+            }
+        };
+
+        match cast::<N::Params>(notification.params) {
+            Ok(params) => handler(self.server, params),
+            Err(err) => eprintln!("error: invalid `{}` notification: {err}", N::METHOD),
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use lsp_server::{Connection, Message};
+    use lsp_types::{
+        CodeActionOrCommand, CompletionItemKind, CompletionResponse, DocumentSymbolResponse,
+        GotoDefinitionResponse, HoverContents, Location, Position, Range, SemanticToken,
+        SemanticTokensResult, SymbolKind, TextEdit, Url,
+    };
+    use salsa::ParallelDatabase;
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+
+    use super::{cast, enclosing_call, Analysis, Server, DECLARATION_MODIFIER};
+    use crate::{line_index::LineIndex, Database, SourceProgram};
+
+    const URI: &str = "file:///uwu.ub";
+
+    fn analysis(src: &str) -> Analysis {
+        let db = Database::default();
+        let source = SourceProgram::new(&db, src.to_owned(), "uwu.ub".into());
+        Analysis {
+            db: db.snapshot(),
+            documents: HashMap::from([(Url::parse(URI).unwrap(), source)]),
+        }
+    }
+
+    /// Parameters in the JSON that clients send.
+    fn params<T: DeserializeOwned>(params: Value) -> T {
+        cast(params).expect("invalid parameters")
+    }
+
+    /// The position of the first occurrence of `pattern`.
+    fn position(src: &str, pattern: &str) -> Position {
+        let offset = src.find(pattern).expect("pattern not found");
+        let line_col = LineIndex::new(src).line_col(src, offset);
+        Position::new(line_col.line, line_col.col)
+    }
+
+    /// The position right after the first occurrence of `pattern`.
+    fn after(src: &str, pattern: &str) -> Position {
+        let mut position = position(src, pattern);
+        position.character += pattern.encode_utf16().count() as u32;
+        position
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    fn text_document_position(position: Position) -> Value {
+        json!({ "textDocument": { "uri": URI }, "position": position })
+    }
+
+    #[test]
+    fn goto_definition() {
+        let src = "fn f() {}\nfn main() { f(); }";
+        let response =
+            analysis(src).goto_definition(params(text_document_position(position(src, "f();"))));
+        assert_eq!(
+            response,
+            Some(GotoDefinitionResponse::Scalar(Location::new(
+                Url::parse(URI).unwrap(),
+                range((0, 3), (0, 4))
+            )))
+        );
+        let response =
+            analysis(src).goto_definition(params(text_document_position(position(src, "{ f"))));
+        assert_eq!(response, None);
+    }
+
+    #[test]
+    fn hover() {
+        let src =
+            "struct S {\n    /// The x.\n    x: u64\n}\nfn f(s: S) {\n    let y: ptr u64 = s;\n}";
+        let analysis = analysis(src);
+        let hover = |pattern| {
+            let hover = analysis.hover(params(text_document_position(position(src, pattern))))?;
+            match hover.contents {
+                HoverContents::Markup(markup) => Some((markup.value, hover.range.unwrap())),
+                _ => panic!("hovers are markdown"),
+            }
+        };
+
+        assert_eq!(
+            hover("x:"),
+            Some((
+                "```ub\nx: u64\n```\n\n---\n\nThe x.".to_string(),
+                range((2, 4), (2, 5))
+            ))
+        );
+        assert_eq!(
+            hover("s;"),
+            Some(("```ub\ns: S\n```".to_string(), range((5, 21), (5, 22))))
+        );
+        assert_eq!(
+            hover("y:"),
+            Some((
+                "```ub\nlet y: ptr u64\n```".to_string(),
+                range((5, 8), (5, 9))
+            ))
+        );
+        assert_eq!(hover("{\n    let"), None);
+    }
+
+    #[test]
+    fn completion() {
+        let src = "struct S { x: u64, y: u64 }\nfn f(s: S) {\n    let a = 1;\n    s.\n}";
+        let analysis = analysis(src);
+        let complete =
+            |position| match analysis.completion(params(text_document_position(position))) {
+                Some(CompletionResponse::Array(items)) => items
+                    .into_iter()
+                    .map(|item| (item.label, item.kind.unwrap()))
+                    .collect::<Vec<_>>(),
+                _ => panic!("no completions"),
+            };
+
+        assert_eq!(
+            complete(after(src, "s.")),
+            [
+                ("x".to_string(), CompletionItemKind::FIELD),
+                ("y".to_string(), CompletionItemKind::FIELD)
+            ]
+        );
+
+        let items = complete(position(src, "s.\n"));
+        for item in [
+            ("S", CompletionItemKind::STRUCT),
+            ("a", CompletionItemKind::VARIABLE),
+            ("f", CompletionItemKind::FUNCTION),
+            ("s", CompletionItemKind::VARIABLE),
+            ("let", CompletionItemKind::KEYWORD),
+        ] {
+            assert!(items.contains(&(item.0.to_string(), item.1)), "{item:?}");
+        }
+        // Fields can only be named after a `.`.
+        assert!(!items.iter().any(|(label, _)| label == "x"));
+    }
+
+    #[test]
+    fn signature_help() {
+        let src = "/// Adds.\nfn add(a: u64, b: u64) {}\nfn main() {\n    add(1, \n}";
+        let analysis = analysis(src);
+        let help = |position| analysis.signature_help(params(text_document_position(position)));
+
+        let help_second = help(after(src, "add(1, ")).expect("no signature help");
+        let signature = &help_second.signatures[0];
+        assert_eq!(signature.label, "fn add(a: u64, b: u64)");
+        assert_eq!(
+            serde_json::to_value(&signature.parameters).unwrap(),
+            json!([{ "label": [7, 13] }, { "label": [15, 21] }])
+        );
+        assert_eq!(help_second.active_parameter, Some(1));
+
+        let help_first = help(after(src, "    add(")).expect("no signature help");
+        assert_eq!(help_first.active_parameter, Some(0));
+        assert!(help(position(src, "    add(")).is_none());
+    }
+
+    #[test]
+    fn enclosing_calls() {
+        fn callee(text: &str) -> Option<(&str, u32)> {
+            enclosing_call(text).map(|(span, argument)| (&text[span], argument))
+        }
+        assert_eq!(callee("f(a, g(b, c"), Some(("g", 1)));
+        assert_eq!(callee("f(a, g(b), "), Some(("f", 2)));
+        assert_eq!(callee("f(a, [1, 2], {"), None);
+        assert_eq!(callee("f([1, 2"), None);
+        assert_eq!(callee("f(a); g"), None);
+        assert_eq!(callee("(a, "), None);
+    }
+
+    #[test]
+    fn document_symbols() {
+        let src = "fn f(a: u64) -> u64 {}\nstruct S {\n    x: u64\n}";
+        let response = analysis(src)
+            .document_symbols(params(json!({ "textDocument": { "uri": URI } })))
+            .expect("no symbols");
+        let symbols = match response {
+            DocumentSymbolResponse::Nested(symbols) => symbols,
+            DocumentSymbolResponse::Flat(_) => panic!("symbols are nested"),
+        };
+
+        assert_eq!(symbols.len(), 2);
+        let (f, s) = (&symbols[0], &symbols[1]);
+        assert_eq!(
+            (&*f.name, f.kind, f.detail.as_deref()),
+            ("f", SymbolKind::FUNCTION, Some("fn f(a: u64) -> u64"))
+        );
+        assert_eq!(
+            (f.range, f.selection_range),
+            (range((0, 0), (0, 22)), range((0, 3), (0, 4)))
+        );
+        assert_eq!(f.children, None);
+
+        assert_eq!(
+            (&*s.name, s.kind, s.detail.as_deref()),
+            ("S", SymbolKind::STRUCT, None)
+        );
+        assert_eq!(s.range, range((1, 0), (3, 1)));
+        let x = &s.children.as_ref().unwrap()[0];
+        assert_eq!(
+            (&*x.name, x.kind, x.detail.as_deref()),
+            ("x", SymbolKind::FIELD, Some("u64"))
+        );
+        assert_eq!(
+            (x.range, x.selection_range),
+            (range((2, 4), (2, 10)), range((2, 4), (2, 5)))
+        );
+    }
+
+    #[test]
+    fn semantic_tokens() {
+        // The string is 8 bytes but 5 UTF-16 code units long.
+        let src = "fn f(a: u64) {\n    let s = \"ä😀\"; let b = a;\n}";
+        let response = analysis(src)
+            .semantic_tokens(params(json!({ "textDocument": { "uri": URI } })))
+            .expect("no tokens");
+        let data = match response {
+            SemanticTokensResult::Tokens(tokens) => tokens.data,
+            SemanticTokensResult::Partial(_) => panic!("tokens are sent at once"),
+        };
+
+        let token = |delta_line, delta_start, token_type, declaration| SemanticToken {
+            delta_line,
+            delta_start,
+            length: 1,
+            token_type,
+            token_modifiers_bitset: if declaration { DECLARATION_MODIFIER } else { 0 },
+        };
+        assert_eq!(
+            data,
+            [
+                token(0, 3, 0, true),  // f
+                token(0, 2, 3, true),  // a
+                token(1, 8, 4, true),  // s, relative to the start of the line
+                token(0, 15, 4, true), // b
+                token(0, 4, 3, false), // a
+            ]
+        );
+    }
+
+    #[test]
+    fn references() {
+        let src = "fn f() {}\nfn main() { f(); f(); }";
+        let references = |include_declaration| {
+            let mut params_json = text_document_position(position(src, "f()"));
+            params_json["context"] = json!({ "includeDeclaration": include_declaration });
+            analysis(src)
+                .references(params(params_json))
+                .expect("no references")
+                .into_iter()
+                .map(|location| location.range)
+                .collect::<Vec<_>>()
+        };
+
+        let uses = [range((1, 12), (1, 13)), range((1, 17), (1, 18))];
+        assert_eq!(references(false), uses);
+        assert_eq!(references(true), [range((0, 3), (0, 4)), uses[0], uses[1]]);
+    }
+
+    #[test]
+    fn code_actions() {
+        let src = "fn main() {\n    let x = 1\n}\nfn g() {}";
+        let actions = |range: Range| {
+            analysis(src)
+                .code_actions(params(json!({
+                    "textDocument": { "uri": URI },
+                    "range": range,
+                    "context": { "diagnostics": [] },
+                })))
+                .expect("no code actions")
+        };
+
+        let fixes = actions(range((0, 0), (3, 0)));
+        assert_eq!(fixes.len(), 1);
+        let action = match &fixes[0] {
+            CodeActionOrCommand::CodeAction(action) => action,
+            CodeActionOrCommand::Command(_) => panic!("fixes are code actions"),
+        };
+        assert_eq!(action.title, "Insert missing `;`");
+        let edits =
+            &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&Url::parse(URI).unwrap()];
+        assert_eq!(
+            edits,
+            &[TextEdit::new(range((2, 0), (2, 0)), ";".to_string())]
+        );
+
+        // Only errors in the range are fixed.
+        assert!(actions(range((3, 0), (3, 9))).is_empty());
+    }
+
+    #[test]
+    fn formatting() {
+        let format = |src: &str| {
+            analysis(src).formatting(params(json!({
+                "textDocument": { "uri": URI },
+                "options": { "tabSize": 4, "insertSpaces": true },
+            })))
+        };
+
+        let src = "fn f( ) { let x=1; // one\n}\n";
+        assert_eq!(
+            format(src),
+            Ok(Some(vec![TextEdit::new(
+                range((0, 0), (2, 0)),
+                "fn f() {\n    let x = 1; // one\n}\n".to_string()
+            )]))
+        );
+        assert_eq!(format("fn f() {}\n"), Ok(Some(Vec::new())));
+        assert_eq!(
+            format("fn f() { let }"),
+            Err("files with syntax errors can't be formatted".to_string())
+        );
+    }
+
+    #[test]
+    fn range_formatting() {
+        let src = "fn a() {  }\nfn b() {  } // b\nfn c() {  }\n";
+        let edits = analysis(src).range_formatting(params(json!({
+            "textDocument": { "uri": URI },
+            "range": range((1, 2), (1, 3)),
+            "options": { "tabSize": 4, "insertSpaces": true },
+        })));
+        // The comment behind the item isn't part of it.
+        assert_eq!(
+            edits,
+            Ok(Some(vec![TextEdit::new(
+                range((1, 0), (1, 11)),
+                "fn b() {}".to_string()
+            )]))
+        );
+    }
+
+    #[test]
+    fn on_type_formatting() {
+        let src = "fn a() {  }\nfn b() { let x=1; }\n";
+        let mut params_json = text_document_position(after(src, "x=1; }"));
+        params_json["ch"] = json!("}");
+        params_json["options"] = json!({ "tabSize": 4, "insertSpaces": true });
+        assert_eq!(
+            analysis(src).on_type_formatting(params(params_json)),
+            Ok(Some(vec![TextEdit::new(
+                range((1, 0), (1, 19)),
+                "fn b() {\n    let x = 1;\n}".to_string()
+            )]))
+        );
+    }
+
+    fn rename(src: &str, position: Position, new_name: &str) -> Result<(), String> {
+        let mut params_json = text_document_position(position);
+        params_json["newName"] = json!(new_name);
+        analysis(src)
+            .rename(params(params_json))
+            .map(|edit| assert!(edit.is_some()))
+    }
+
+    #[test]
+    fn structs_cant_be_renamed_to_bool() {
+        let src = "struct S { x: u64 }\nfn f(s: S) { let y = s; }";
+        assert_eq!(
+            rename(src, Position::new(0, 7), "bool"),
+            Err("`bool` is a primitive type".to_string())
+        );
+        assert_eq!(rename(src, Position::new(0, 7), "T"), Ok(()));
+        // Only types can't be named `bool`.
+        assert_eq!(rename(src, Position::new(1, 17), "bool"), Ok(()));
+    }
+
+    /// A server that isn't running, and the client end of its connection.
+    fn server() -> (Server, Connection) {
+        let (connection, client) = Connection::memory();
+        let server = Server {
+            connection,
+            db: Database::default(),
+            documents: HashMap::new(),
+            stale_diagnostics: Arc::default(),
+            published_diagnostics: Arc::default(),
+        };
+        (server, client)
+    }
+
+    fn open(server: &mut Server, text: &str) {
+        server.did_open(params(json!({
+            "textDocument": { "uri": URI, "languageId": "ub", "version": 1, "text": text },
+        })));
+    }
+
+    fn text(server: &Server) -> String {
+        let source = server.documents[&Url::parse(URI).unwrap()];
+        source.text(&server.db).clone()
+    }
+
+    #[test]
+    fn incremental_changes() {
+        let (mut server, _client) = server();
+        open(&mut server, "let s = \"😀\";\nfn f() {}\r\nx");
+        server.did_change(params(json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [
+                // Columns are in UTF-16 code units, so the emoji is two columns wide.
+                { "range": range((0, 11), (0, 11)), "text": "!" },
+                // Relative to the text after the first change.
+                { "range": range((0, 9), (0, 12)), "text": "ä" },
+                // Across lines, and past the end of the line before the `\r\n`.
+                { "range": range((1, 3), (2, 0)), "text": "g() {}\n" },
+                { "range": range((2, 1), (2, 5)), "text": "y" },
+            ],
+        })));
+        assert_eq!(text(&server), "let s = \"ä\";\nfn g() {}\nxy");
+
+        server.did_change(params(json!({
+            "textDocument": { "uri": URI, "version": 3 },
+            "contentChanges": [{ "text": "fn main() {}" }],
+        })));
+        assert_eq!(text(&server), "fn main() {}");
+    }
+
+    #[test]
+    fn closing_clears_diagnostics() {
+        let (mut server, client) = server();
+        open(&mut server, "fn f() { let }");
+        server.did_close(params(json!({ "textDocument": { "uri": URI } })));
+
+        assert!(server.documents.is_empty());
+        assert!(server.stale_diagnostics.lock().unwrap().is_empty());
+        assert!(server.published_diagnostics.lock().unwrap().is_empty());
+        match client.receiver.try_recv() {
+            Ok(Message::Notification(notification)) => assert_eq!(
+                notification.params,
+                json!({ "uri": URI, "diagnostics": [] })
+            ),
+            message => panic!("expected a notification, got {message:?}"),
+        }
+    }
+}
@@ -1,3 +1,5 @@
-fn main() {
-    ub::test();
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    ub::driver::main()
 }
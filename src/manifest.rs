@@ -0,0 +1,258 @@
+//! The `ub.toml` project manifest. When the driver is invoked without input files, it looks for
+//! a manifest in the current directory and its parents and compiles the files of the project.
+//!
+//! ```toml
+//! [package]
+//! name = "hello"
+//! # Directories containing the `.ub` files of the package, relative to the manifest.
+//! # Defaults to `["src"]`.
+//! sources = ["src"]
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+pub const MANIFEST_FILE_NAME: &str = "ub.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: Package,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    pub name: String,
+    #[serde(default = "default_sources")]
+    pub sources: Vec<PathBuf>,
+}
+
+fn default_sources() -> Vec<PathBuf> {
+    vec![PathBuf::from("src")]
+}
+
+/// Looks for a manifest in `dir` and all of its ancestors.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(MANIFEST_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+pub fn load(path: &Path) -> Result<Manifest, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    toml::from_str(&text).map_err(|err| err.to_string())
+}
+
+impl Manifest {
+    /// Collects all `.ub` files in the source directories, sorted so that the order doesn't
+    /// depend on the file system. `root` is the directory containing the manifest. Files in
+    /// overlapping source directories are only listed once.
+    pub fn source_files(&self, root: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for dir in &self.package.sources {
+            // Without `.` components, `src` and `./src` find the same paths.
+            let dir = root.join(dir).components().collect::<PathBuf>();
+            collect_source_files(&dir, &mut files)?;
+        }
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+}
+
+/// Symlinks to directories aren't followed, since they could point back up and make a cycle.
+fn collect_source_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_source_files(&path, files)?;
+        } else if path.extension() == Some("ub".as_ref()) && path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        process,
+    };
+
+    use super::{find, load, Manifest, Package, MANIFEST_FILE_NAME};
+
+    /// A fresh directory for one test, removed again when the test is done.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("ub-manifest-{}-{name}", process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn write(&self, path: &str, content: &str) -> PathBuf {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn relative(files: Vec<PathBuf>, root: &Path) -> Vec<String> {
+        files
+            .iter()
+            .map(|file| file.strip_prefix(root).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn find_walks_up_to_the_closest_manifest() {
+        let dir = TempDir::new("find");
+        let outer = dir.write(MANIFEST_FILE_NAME, "");
+        let inner = dir.write("inner/ub.toml", "");
+        fs::create_dir_all(dir.0.join("inner/src/deep")).unwrap();
+
+        assert_eq!(find(&dir.0.join("inner/src/deep")), Some(inner));
+        assert_eq!(
+            find(&dir.0.join("inner")),
+            Some(dir.0.join("inner/ub.toml"))
+        );
+        fs::create_dir_all(dir.0.join("other")).unwrap();
+        assert_eq!(find(&dir.0.join("other")), Some(outer));
+    }
+
+    #[test]
+    fn find_ignores_directories_named_like_the_manifest() {
+        let dir = TempDir::new("find-dir");
+        fs::create_dir_all(dir.0.join("sub/ub.toml")).unwrap();
+        let manifest = dir.write(MANIFEST_FILE_NAME, "");
+
+        assert_eq!(find(&dir.0.join("sub")), Some(manifest));
+    }
+
+    #[test]
+    fn load_defaults_sources() {
+        let dir = TempDir::new("load");
+        let path = dir.write(MANIFEST_FILE_NAME, "[package]\nname = \"hello\"\n");
+
+        let manifest = load(&path).unwrap();
+        assert_eq!(
+            manifest,
+            Manifest {
+                package: Package {
+                    name: "hello".to_owned(),
+                    sources: vec![PathBuf::from("src")],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn load_rejects_malformed_manifests() {
+        let dir = TempDir::new("malformed");
+        for (i, text) in [
+            "[package",
+            "[package]\nsources = [\"src\"]\n",
+            "[package]\nname = \"hello\"\nversion = \"1.0\"\n",
+            "[package]\nname = \"hello\"\nsources = \"src\"\n",
+            "name = \"hello\"\n",
+        ]
+        .iter()
+        .enumerate()
+        {
+            let path = dir.write(&format!("{i}.toml"), text);
+            assert!(load(&path).is_err(), "{text:?} was accepted");
+        }
+        assert!(load(&dir.0.join("missing.toml")).is_err());
+    }
+
+    #[test]
+    fn source_files_are_sorted_across_directories() {
+        let dir = TempDir::new("sources");
+        dir.write("src/main.ub", "");
+        dir.write("src/b/z.ub", "");
+        dir.write("src/b/a.ub", "");
+        dir.write("src/notes.txt", "");
+        dir.write("lib/util.ub", "");
+        dir.write("ignored/other.ub", "");
+
+        let manifest = Manifest {
+            package: Package {
+                name: "hello".to_owned(),
+                sources: vec![PathBuf::from("src"), PathBuf::from("lib")],
+            },
+        };
+        let files = manifest.source_files(&dir.0).unwrap();
+        assert_eq!(
+            relative(files, &dir.0),
+            ["lib/util.ub", "src/b/a.ub", "src/b/z.ub", "src/main.ub"]
+        );
+    }
+
+    #[test]
+    fn missing_source_directory_is_an_error() {
+        let dir = TempDir::new("missing-sources");
+        let manifest = Manifest {
+            package: Package {
+                name: "hello".to_owned(),
+                sources: vec![PathBuf::from("src")],
+            },
+        };
+        assert!(manifest.source_files(&dir.0).is_err());
+    }
+
+    #[test]
+    fn overlapping_source_directories() {
+        let dir = TempDir::new("overlapping");
+        dir.write("src/main.ub", "");
+        dir.write("src/b/a.ub", "");
+
+        let manifest = Manifest {
+            package: Package {
+                name: "hello".to_owned(),
+                sources: ["src", "src/b", "./src"].map(PathBuf::from).to_vec(),
+            },
+        };
+        let files = manifest.source_files(&dir.0).unwrap();
+        assert_eq!(relative(files, &dir.0), ["src/b/a.ub", "src/main.ub"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directories_arent_followed() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new("symlinks");
+        dir.write("src/main.ub", "");
+        dir.write("other/lib.ub", "");
+        symlink(&dir.0, dir.0.join("src/cycle")).unwrap();
+        symlink(dir.0.join("other"), dir.0.join("src/other")).unwrap();
+        // Symlinks to files are still sources.
+        symlink(dir.0.join("other/lib.ub"), dir.0.join("src/lib.ub")).unwrap();
+
+        let manifest = Manifest {
+            package: Package {
+                name: "hello".to_owned(),
+                sources: vec![PathBuf::from("src")],
+            },
+        };
+        let files = manifest.source_files(&dir.0).unwrap();
+        assert_eq!(relative(files, &dir.0), ["src/lib.ub", "src/main.ub"]);
+    }
+}
@@ -1,16 +1,16 @@
 use std::{cell::Cell, ops::Range, path::PathBuf};
 
-use chumsky::{prelude::*, Stream};
+use chumsky::{error::SimpleReason, prelude::*, Stream};
 use logos::Logos;
 
 use crate::{
     ast::{
         Assignment, BinOp, BinOpKind, Call, ElsePart, Expr, ExprKind, File, FnDecl, IfStmt, Item,
-        Literal, NameTyPair, NodeId, Stmt, StructDecl, Ty, TyKind, UnaryOp, UnaryOpKind, VarDecl,
-        WhileStmt,
+        Literal, NameTyPair, NodeId, ReturnStmt, Stmt, StructDecl, Ty, TyKind, UnaryOp,
+        UnaryOpKind, VarDecl, WhileStmt,
     },
     lexer::Token,
-    Db, Diagnostics, SourceProgram,
+    timing, Db, Diagnostics, SourceProgram,
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -18,6 +18,79 @@ pub struct Error(pub chumsky::error::Simple<Token>);
 
 impl Eq for Error {}
 
+/// An edit that fixes an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Error {
+    pub fn span(&self) -> Span {
+        self.0.span()
+    }
+
+    /// A single line description, for places that can't render a full report.
+    pub fn message(&self) -> String {
+        match self.0.reason() {
+            SimpleReason::Unclosed { delimiter, .. } => format!("Unclosed delimiter {delimiter}"),
+            SimpleReason::Unexpected => {
+                let found = match self.0.found() {
+                    Some(token) => format!("Unexpected token {token}"),
+                    None => "Unexpected end of input".to_string(),
+                };
+                let expected = self.expected();
+                if expected.is_empty() {
+                    format!("{found}, expected something else")
+                } else {
+                    format!("{found}, expected {}", expected.join(", "))
+                }
+            }
+            SimpleReason::Custom(msg) => msg.clone(),
+        }
+    }
+
+    /// Sorted, since chumsky stores them in a hash set whose order can change between builds.
+    pub fn expected(&self) -> Vec<String> {
+        let mut expected = self
+            .0
+            .expected()
+            .map(|expected| match expected {
+                Some(expected) => expected.to_string(),
+                None => "end of input".to_string(),
+            })
+            .collect::<Vec<_>>();
+        expected.sort();
+        expected
+    }
+
+    /// Only errors with a single obvious fix have a suggestion.
+    pub fn suggestion(&self) -> Option<Suggestion> {
+        let insert = |token: Token| Suggestion {
+            message: format!("Insert missing `{token}`"),
+            span: self.span().start..self.span().start,
+            replacement: token.to_string(),
+        };
+        match self.0.reason() {
+            SimpleReason::Unclosed { delimiter, .. } => match delimiter {
+                Token::BraceO => Some(insert(Token::BraceC)),
+                Token::ParenO => Some(insert(Token::ParenC)),
+                Token::BracketO => Some(insert(Token::BracketC)),
+                _ => None,
+            },
+            // A trailing `.` is an unfinished field access, which a semicolon doesn't fix.
+            SimpleReason::Unexpected
+                if self.0.found() != Some(&Token::Dot)
+                    && self.0.expected().eq([&Some(Token::Semi)]) =>
+            {
+                Some(insert(Token::Semi))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl chumsky::Error<Token> for Error {
     type Span = Span;
     type Label = &'static str;
@@ -59,10 +132,16 @@ fn ident_parser() -> impl Parser<Token, String, Error = Error> + Clone {
     ident.labelled("identifier").boxed()
 }
 
+/// An identifier together with its span, for names that are declared.
+fn spanned_ident_parser() -> impl Parser<Token, (String, Span), Error = Error> + Clone {
+    ident_parser().map_with_span(|name, span| (name, span))
+}
+
 fn ty_parser() -> impl Parser<Token, Ty, Error = Error> + Clone {
     recursive(|ty_parser| {
         let primitive = filter_map(|span, token| {
             let kind = match token {
+                Token::Ident(name) if name == "bool" => TyKind::Bool,
                 Token::Ident(name) => TyKind::Name(name),
                 _ => {
                     return Err(Error(Simple::expected_input_found(
@@ -102,18 +181,23 @@ fn expr_parser<'src>(
         let literal = filter_map(|span: Span, token| match token {
             Token::String(str) => Ok(Expr {
                 kind: ExprKind::Literal(Literal::String(
-                    str[1..str.len() - 2].to_owned(),
+                    str[1..str.len() - 1].to_owned(),
                     span.clone(),
                 )),
                 id: state.next_id(),
                 span,
             }),
-            // todo lol unwrap
+            // Integers that don't fit into a `u64` are lexed as `Token::Error`.
             Token::Integer(int) => Ok(Expr {
                 kind: ExprKind::Literal(Literal::Integer(int, span.clone())),
                 id: state.next_id(),
                 span,
             }),
+            Token::True | Token::False => Ok(Expr {
+                kind: ExprKind::Literal(Literal::Bool(token == Token::True, span.clone())),
+                id: state.next_id(),
+                span,
+            }),
             _ => Err(Error(Simple::expected_input_found(
                 span,
                 Vec::new(),
@@ -196,9 +280,11 @@ fn expr_parser<'src>(
         .labelled("unary")
         .boxed();
 
-        let op = just(Token::Asterisk)
-            .to(BinOpKind::Mul)
-            .or(just(Token::Slash).to(BinOpKind::Div));
+        let op = choice((
+            just(Token::Asterisk).to(BinOpKind::Mul),
+            just(Token::Slash).to(BinOpKind::Div),
+            just(Token::Percent).to(BinOpKind::Rem),
+        ));
 
         let product = unary_op
             .clone()
@@ -240,13 +326,39 @@ fn expr_parser<'src>(
             .labelled("product")
             .boxed();
 
+        // Ordering ops bind tighter than equality, so `a < b == c < d` compares two booleans
+        let op = choice((
+            just(Token::Less).to(BinOpKind::Lt),
+            just(Token::Greater).to(BinOpKind::Gt),
+            just(Token::LessEq).to(BinOpKind::LtEq),
+            just(Token::GreaterEq).to(BinOpKind::GtEq),
+        ));
+        let relational = sum
+            .clone()
+            .then(op.then(sum).repeated())
+            .foldl(|a, (kind, b)| {
+                let span = a.span.start..b.span.end;
+                Expr {
+                    kind: ExprKind::BinOp(BinOp {
+                        kind,
+                        lhs: Box::new(a),
+                        rhs: Box::new(b),
+                        span: span.clone(),
+                    }),
+                    id: state.next_id(),
+                    span,
+                }
+            })
+            .labelled("relational comparison")
+            .boxed();
+
         // Comparison ops (equal, not-equal) have equal precedence
         let op = just(Token::EqEq)
             .to(BinOpKind::Eq)
             .or(just(Token::BangEq).to(BinOpKind::Neq));
-        let compare = sum
+        let compare = relational
             .clone()
-            .then(op.then(sum).repeated())
+            .then(op.then(relational).repeated())
             .foldl(|a, (kind, b)| {
                 let span = a.span.start..b.span.end;
                 Expr {
@@ -260,25 +372,92 @@ fn expr_parser<'src>(
                     span,
                 }
             });
-        compare.labelled("comparison").boxed()
+        let compare = compare.labelled("comparison").boxed();
+
+        // `&&` binds tighter than `||`, like in Rust and C
+        let and = compare
+            .clone()
+            .then(
+                just(Token::AndAnd)
+                    .to(BinOpKind::And)
+                    .then(compare)
+                    .repeated(),
+            )
+            .foldl(|a, (kind, b)| {
+                let span = a.span.start..b.span.end;
+                Expr {
+                    kind: ExprKind::BinOp(BinOp {
+                        kind,
+                        lhs: Box::new(a),
+                        rhs: Box::new(b),
+                        span: span.clone(),
+                    }),
+                    id: state.next_id(),
+                    span,
+                }
+            })
+            .labelled("logical and")
+            .boxed();
+
+        let or = and
+            .clone()
+            .then(just(Token::OrOr).to(BinOpKind::Or).then(and).repeated())
+            .foldl(|a, (kind, b)| {
+                let span = a.span.start..b.span.end;
+                Expr {
+                    kind: ExprKind::BinOp(BinOp {
+                        kind,
+                        lhs: Box::new(a),
+                        rhs: Box::new(b),
+                        span: span.clone(),
+                    }),
+                    id: state.next_id(),
+                    span,
+                }
+            });
+        or.labelled("logical or").boxed()
     })
 }
 
+/// The semicolon at the end of a statement. A missing semicolon right before the end of a block
+/// is reported without failing, so that the rest of the function can still be analyzed while it
+/// is being typed. Field accesses aren't parsed yet, so a trailing `.` or `.field` is included.
+fn semicolon_parser() -> impl Parser<Token, (), Error = Error> + Clone {
+    let missing = just(Token::Dot)
+        .ignore_then(ident_parser().or_not())
+        .or_not()
+        .then_ignore(just(Token::BraceC).rewind())
+        .validate(|field_access, span: Span, emit| {
+            let found = match field_access {
+                Some(_) => Token::Dot,
+                None => Token::BraceC,
+            };
+            emit(Error(Simple::expected_input_found(
+                span,
+                [Some(Token::Semi)],
+                Some(found),
+            )))
+        });
+
+    just(Token::Semi).ignored().or(missing)
+}
+
 fn statement_parser<'src>(
     state: &'src ParserState,
 ) -> impl Parser<Token, Stmt, Error = Error> + Clone + 'src {
     recursive(|stmt| {
         let var_decl = just(Token::Let)
-            .ignore_then(ident_parser())
+            .ignore_then(spanned_ident_parser())
             .then(just(Token::Colon).ignore_then(ty_parser()).or_not())
             .then(just(Token::Eq).ignore_then(expr_parser(state)).or_not())
-            .then_ignore(just(Token::Semi))
-            .map(|((name, ty), rhs)| {
+            .then_ignore(semicolon_parser())
+            .map_with_span(|(((name, name_span), ty), rhs), span| {
                 Stmt::VarDecl(VarDecl {
                     name,
+                    name_span,
                     ty,
                     rhs,
-                    span: Default::default(),
+                    span,
                 })
             })
             .boxed();
@@ -286,14 +465,24 @@ fn statement_parser<'src>(
         let assignment = expr_parser(state)
             .then_ignore(just(Token::Eq))
             .then(expr_parser(state))
-            .then_ignore(just(Token::Semi))
-            .map(|(place, rhs)| {
-                Stmt::Assignment(Assignment {
-                    place,
-                    rhs,
-                    span: Default::default(),
-                })
-            });
+            .then_ignore(semicolon_parser())
+            .map_with_span(|(place, rhs), span| Stmt::Assignment(Assignment { place, rhs, span }));
+
+        let return_stmt = just(Token::Return)
+            .ignore_then(expr_parser(state).or_not())
+            .then_ignore(semicolon_parser())
+            .map_with_span(|expr, span| Stmt::Return(ReturnStmt { expr, span }))
+            .labelled("return");
+
+        let break_stmt = just(Token::Break)
+            .ignore_then(semicolon_parser())
+            .map_with_span(|(), span| Stmt::Break(span))
+            .labelled("break");
+
+        let continue_stmt = just(Token::Continue)
+            .ignore_then(semicolon_parser())
+            .map_with_span(|(), span| Stmt::Continue(span))
+            .labelled("continue");
 
         let block = stmt
             .clone()
@@ -330,9 +519,12 @@ fn statement_parser<'src>(
         .boxed();
 
         var_decl
+            .or(return_stmt)
+            .or(break_stmt)
+            .or(continue_stmt)
             .or(assignment)
             .or(expr_parser(state)
-                .then_ignore(just(Token::Semi))
+                .then_ignore(semicolon_parser())
                 .map(Stmt::Expr))
             .or(if_stmt)
             .or(while_loop)
@@ -341,35 +533,75 @@ fn statement_parser<'src>(
     .boxed()
 }
 
+/// Consecutive `///` lines, joined with newlines. A single leading space is removed from each line.
+fn doc_comment_parser() -> impl Parser<Token, Option<String>, Error = Error> + Clone {
+    select! {
+        Token::DocComment(line) => line,
+    }
+    .repeated()
+    .map(|lines: Vec<String>| {
+        if lines.is_empty() {
+            return None;
+        }
+        let lines = lines
+            .iter()
+            .map(|line| line.strip_prefix(' ').unwrap_or(line))
+            .collect::<Vec<_>>();
+        Some(lines.join("\n"))
+    })
+    .labelled("doc comment")
+}
+
 fn name_ty_pair_parser<'src>(
     state: &'src ParserState,
 ) -> impl Parser<Token, NameTyPair, Error = Error> + Clone + 'src {
-    ident_parser()
+    spanned_ident_parser()
         .then_ignore(just(Token::Colon))
         .then(ty_parser())
-        .map_with_span(|(name, ty), span| NameTyPair {
+        .map_with_span(|((name, name_span), ty), span| NameTyPair {
             name,
+            name_span,
             ty,
             id: state.next_id(),
             span,
+            doc: None,
         })
 }
 
 fn struct_parser<'src>(
     state: &'src ParserState,
 ) -> impl Parser<Token, StructDecl, Error = Error> + Clone + 'src {
-    let name = just(Token::Struct).ignore_then(ident_parser());
+    // `bool` in a type is always the primitive, so a struct with that name couldn't be used.
+    let name = just(Token::Struct)
+        .ignore_then(spanned_ident_parser())
+        .validate(|(name, name_span), _, emit| {
+            if name == "bool" {
+                emit(Error(Simple::custom(
+                    name_span.clone(),
+                    "`bool` is a primitive type and can't be the name of a struct",
+                )));
+            }
+            (name, name_span)
+        });
+
+    let field = doc_comment_parser()
+        .then(name_ty_pair_parser(state))
+        .map(|(doc, field)| NameTyPair { doc, ..field });
 
-    let fields = name_ty_pair_parser(state)
+    let fields = field
         .separated_by(just(Token::Comma))
         .delimited_by(just(Token::BraceO), just(Token::BraceC));
 
-    name.then(fields)
-        .map(|(name, fields)| StructDecl {
+    doc_comment_parser()
+        .then(name)
+        .then(fields)
+        .map_with_span(|((doc, (name, name_span)), fields), span| StructDecl {
             name,
+            name_span,
             fields,
             id: state.next_id(),
-            span: Default::default(),
+            span,
+            doc,
         })
         .labelled("struct")
 }
@@ -379,7 +611,7 @@ fn item_parser<'src>(
 ) -> impl Parser<Token, Item, Error = Error> + Clone + 'src {
     // ---- function
 
-    let name = ident_parser();
+    let name = spanned_ident_parser();
 
     let params = name_ty_pair_parser(state)
         .separated_by(just(Token::Comma))
@@ -388,23 +620,38 @@ fn item_parser<'src>(
         .labelled("function arguments");
 
     let ret_ty = just(Token::Arrow).ignore_then(ty_parser()).or_not();
-    let function = just(Token::Fn)
-        .ignore_then(name)
+    let function = doc_comment_parser()
+        .then_ignore(just(Token::Fn))
+        .then(name)
         .then(params)
         .then(ret_ty)
         .then(
             statement_parser(state)
                 .repeated()
-                .delimited_by(just(Token::BraceO), just(Token::BraceC)),
+                .delimited_by(just(Token::BraceO), just(Token::BraceC))
+                // Keep the signature of functions with broken bodies around for the editor.
+                .recover_with(nested_delimiters(
+                    Token::BraceO,
+                    Token::BraceC,
+                    [
+                        (Token::ParenO, Token::ParenC),
+                        (Token::BracketO, Token::BracketC),
+                    ],
+                    |_| Vec::new(),
+                )),
+        )
+        .map_with_span(
+            |((((doc, (name, name_span)), params), ret_ty), body), span| FnDecl {
+                name,
+                name_span,
+                params,
+                ret_ty,
+                id: state.next_id(),
+                span,
+                body,
+                doc,
+            },
         )
-        .map_with_span(|(((name, params), ret_ty), body), span| FnDecl {
-            name,
-            params,
-            ret_ty,
-            id: state.next_id(),
-            span,
-            body,
-        })
         .labelled("function");
 
     // ---- item
@@ -429,14 +676,174 @@ fn file_parser<'src>(
         .labelled("file")
 }
 
+/// Drops the doc comments that don't document an item or a struct field. They used to be normal
+/// comments, and the grammar only has room for them in front of items and fields.
+fn strip_stray_doc_comments(tokens: Vec<(Token, Span)>) -> Vec<(Token, Span)> {
+    let stray = stray_doc_comments(&tokens);
+    tokens
+        .into_iter()
+        .zip(stray)
+        .filter(|(_, stray)| !stray)
+        .map(|(token, _)| token)
+        .collect()
+}
+
+/// For each token, whether it is a doc comment that doesn't document an item or a struct field.
+/// The parser drops those, so the formatter has to keep them like normal comments.
+pub(crate) fn stray_doc_comments(tokens: &[(Token, Span)]) -> Vec<bool> {
+    // For each token, the first token from there on that isn't a doc comment. Found in one pass,
+    // so that long runs of doc comments aren't scanned once per line.
+    let mut next = vec![None; tokens.len()];
+    let mut following = None;
+    for (i, (token, _)) in tokens.iter().enumerate().rev() {
+        if !matches!(token, Token::DocComment(_)) {
+            following = Some(token);
+        }
+        next[i] = following;
+    }
+
+    // For each open brace, whether it opens the fields of a struct.
+    let mut braces = Vec::new();
+    let mut stray = Vec::with_capacity(tokens.len());
+    for (i, (token, _)) in tokens.iter().enumerate() {
+        match token {
+            Token::BraceO => {
+                let struct_fields = i >= 2 && tokens[i - 2].0 == Token::Struct;
+                braces.push(struct_fields);
+            }
+            Token::BraceC => {
+                braces.pop();
+            }
+            _ => {}
+        }
+        let documents = match next[i] {
+            Some(Token::Fn | Token::Struct) => true,
+            Some(Token::Ident(_)) => braces.last() == Some(&true),
+            _ => false,
+        };
+        stray.push(matches!(token, Token::DocComment(_)) && !documents);
+    }
+    stray
+}
+
+/// Trees deeper than this could overflow the stack in later passes, which all recurse.
+const NESTING_LIMIT: usize = 256;
+
+/// The first token at which the tree might get deeper than [`NESTING_LIMIT`]. The depth is
+/// overestimated: every delimiter, operator, `ptr`, `if` and `else` counts as a level until the
+/// delimiter is closed or the list element or statement ends. Calls count until the list element
+/// or statement that they are in ends, since `f()()` is a call of a call.
+fn too_deep(tokens: &[(Token, Span)]) -> Option<Span> {
+    let mut depth = 0;
+    // The depth right after each open delimiter, which is where its elements or statements start.
+    let mut open = Vec::new();
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        match token {
+            Token::ParenO | Token::BracketO if i > 0 && ends_expr(&tokens[i - 1].0) => {
+                // Postfix calls nest their callee, so each one stays a level deeper after the
+                // delimiter is closed.
+                depth += 2;
+                open.push(depth);
+            }
+            Token::BraceO | Token::ParenO | Token::BracketO => {
+                depth += 1;
+                open.push(depth);
+            }
+            Token::BraceC => {
+                let block_depth = open.pop().unwrap_or(1);
+                depth = match tokens.get(i + 1) {
+                    // `else if` chains are nested.
+                    Some((Token::Else, _)) => block_depth - 1,
+                    _ => open.last().copied().unwrap_or(0),
+                };
+            }
+            Token::ParenC | Token::BracketC => depth = open.pop().map_or(0, |depth| depth - 1),
+            Token::Semi | Token::Comma => depth = open.last().copied().unwrap_or(0),
+            Token::EqEq
+            | Token::Bang
+            | Token::BangEq
+            | Token::Greater
+            | Token::Less
+            | Token::GreaterEq
+            | Token::LessEq
+            | Token::Asterisk
+            | Token::Slash
+            | Token::Percent
+            | Token::Plus
+            | Token::Minus
+            | Token::Or
+            | Token::Ampersand
+            | Token::OrOr
+            | Token::AndAnd
+            | Token::Caret
+            | Token::Ptr
+            | Token::If
+            | Token::Else => depth += 1,
+            _ => {}
+        }
+        if depth > NESTING_LIMIT {
+            return Some(span.clone());
+        }
+    }
+    None
+}
+
+/// Whether `token` can be the last token of an expression, so that a `(` after it starts the
+/// arguments of a call.
+fn ends_expr(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Ident(_)
+            | Token::Integer(_)
+            | Token::String(_)
+            | Token::True
+            | Token::False
+            | Token::ParenC
+            | Token::BracketC
+    )
+}
+
 #[salsa::tracked]
 pub fn parse(db: &dyn Db, source: SourceProgram) -> Option<File> {
     let lexer = Token::lexer(source.text(db));
     let len = lexer.source().len();
     let state = ParserState::default();
 
-    let (result, errs) = file_parser(source.file_name(db).clone(), &state)
-        .parse_recovery_verbose(Stream::from_iter(len..len + 1, lexer.spanned()));
+    let tokens = timing::time("lex", || {
+        lexer
+            .spanned()
+            .map(|(token, span)| match token {
+                Token::UnterminatedString(str) => {
+                    Diagnostics::push(
+                        db,
+                        Error(Simple::custom(
+                            span.start..span.start + 1,
+                            "Unterminated string literal",
+                        )),
+                    );
+                    (Token::String(str + "\""), span)
+                }
+                token => (token, span),
+            })
+            .collect::<Vec<_>>()
+    });
+    let tokens = strip_stray_doc_comments(tokens);
+
+    if let Some(span) = too_deep(&tokens) {
+        Diagnostics::push(
+            db,
+            Error(Simple::custom(
+                span,
+                format!("Nested too deeply, at most {NESTING_LIMIT} levels are supported"),
+            )),
+        );
+        return None;
+    }
+
+    let (result, errs) = timing::time("parse", || {
+        file_parser(source.file_name(db).clone(), &state)
+            .parse_recovery_verbose(Stream::from_iter(len..len + 1, tokens.into_iter()))
+    });
 
     for err in errs {
         Diagnostics::push(db, err);
@@ -511,6 +918,42 @@ mod tests {
         insta::assert_debug_snapshot!(r);
     }
 
+    #[test]
+    fn return_stmt() {
+        let r = parse("fn foo() -> u64 { return 1 + 5; return; }");
+        insta::assert_debug_snapshot!(r);
+    }
+
+    #[test]
+    fn break_continue() {
+        let r = parse("fn foo() { while 1 { break; continue; } }");
+        insta::assert_debug_snapshot!(r);
+    }
+
+    #[test]
+    fn bool_literals() {
+        let r = parse("fn foo(b: bool) -> ptr bool { let yes: bool = true; b = false; }");
+        insta::assert_debug_snapshot!(r);
+    }
+
+    #[test]
+    fn logical_ops() {
+        let r = parse("fn foo() { let x = a || b && c == d || !e; }");
+        insta::assert_debug_snapshot!(r);
+    }
+
+    #[test]
+    fn relational_ops() {
+        let r = parse("fn foo() { let x = a < b + 1 == c >= d; let y = a <= b > c; }");
+        insta::assert_debug_snapshot!(r);
+    }
+
+    #[test]
+    fn rem() {
+        let r = parse("fn foo() { let x = a % 3 * b + c % 5; }");
+        insta::assert_debug_snapshot!(r);
+    }
+
     #[test]
     fn var_decl() {
         let r = parse(
@@ -525,9 +968,114 @@ mod tests {
         insta::assert_debug_snapshot!(r);
     }
 
+    #[test]
+    fn struct_named_bool() {
+        let r = parse("struct bool { x: u64 }");
+        insta::assert_debug_snapshot!(r);
+    }
+
     #[test]
     fn types() {
         let r = parse("fn types() -> ptr u64 { let test: Test = 2; let int: ptr u64 = 25; }");
         insta::assert_debug_snapshot!(r);
     }
+
+    #[test]
+    fn nesting_limit() {
+        let r = parse(&format!("fn main() {{ {}1; }}", "-".repeat(300)));
+        insta::assert_debug_snapshot!(r);
+    }
+
+    #[test]
+    fn nesting_limit_parens() {
+        let r = parse(&format!(
+            "fn main() {{ f({}1{}); }}",
+            "(".repeat(300),
+            ")".repeat(300)
+        ));
+        insta::assert_debug_snapshot!(r);
+    }
+
+    #[test]
+    fn nesting_limit_calls() {
+        let r = parse(&format!("fn main() {{ f{}; }}", "()".repeat(300)));
+        insta::assert_debug_snapshot!(r);
+    }
+
+    /// Long lists are wide, not deep, so they don't count against the nesting limit.
+    #[test]
+    fn long_flat_lists() {
+        let fields = (0..300)
+            .map(|i| format!("f{i}: ptr ptr u64"))
+            .collect::<Vec<_>>();
+        let args = (0..300).map(|i| format!("(-{i})")).collect::<Vec<_>>();
+        let src = format!(
+            "struct S {{ {} }}\nfn main() {{ f({}); [{}]; {} }}",
+            fields.join(", "),
+            args.join(", "),
+            args.join(", "),
+            "if a == b { 1; } ".repeat(300)
+        );
+
+        let db = Database::default();
+        let source_program = SourceProgram::new(&db, src, "uwu.ub".into());
+        let file = super::parse(&db, source_program);
+        let errs = super::parse::accumulated::<Diagnostics>(&db, source_program);
+        assert_eq!(errs, []);
+        assert!(file.is_some());
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let r = parse(
+            "fn main() {
+    print(\"open
+    );
+    let b = \"closed\";
+}",
+        );
+        insta::assert_debug_snapshot!(r);
+    }
+
+    #[test]
+    fn doc_comments() {
+        let r = parse(
+            "/// A point.
+///
+/// Lives on a plane.
+struct Point {
+    /// The horizontal part.
+    x: u64,
+    y: u64
+}
+
+/// Adds two numbers.
+fn add(a: u64, b: u64) -> u64 {}",
+        );
+        insta::assert_debug_snapshot!(r);
+    }
+
+    #[test]
+    fn stray_doc_comments() {
+        let r = parse(
+            "//// Banner
+fn add(
+    /// The first one.
+    a: u64,
+) -> u64 {
+    /// Not an item.
+    let b = a;
+    /// Not an item either.
+}
+
+struct Point {
+    /// Kept.
+    x: u64
+    /// After the last field.
+}
+
+/// At the end of the file.",
+        );
+        insta::assert_debug_snapshot!(r);
+    }
 }
@@ -1,49 +1,193 @@
 use std::fmt::Write;
 
-use crate::ast::{
-    BinOpKind, ElsePart, Expr, ExprKind, File, IfStmt, Item, Literal, NameTyPair, Stmt, Ty, TyKind,
-    UnaryOpKind,
+use crate::{
+    ast::{
+        BinOpKind, ElsePart, Expr, ExprKind, File, FnDecl, IfStmt, Item, Literal, NameTyPair, Stmt,
+        StructDecl, Ty, TyKind, UnaryOpKind,
+    },
+    lexer,
+    parser::{self, Span},
 };
 
 pub fn pretty_print_ast(ast: &File) -> String {
-    let mut printer = Printer {
-        out: String::new(),
-        indent: 0,
-    };
+    let mut printer = Printer::default();
+
+    printer.print_items(&ast.items);
+
+    printer.out
+}
 
+/// Like [`pretty_print_ast`], but keeps the comments in `text`, which `ast` was parsed from.
+/// Comments stay in front of or behind the item, field, parameter or statement that they are
+/// next to. Comments inside of expressions move in front of the next statement.
+pub fn pretty_print_ast_with_comments(ast: &File, text: &str) -> String {
+    let mut printer = Printer::with_comments(comments(text));
     printer.print_items(&ast.items);
+    printer.out
+}
+
+/// Includes the doc comment, but not the trailing newline.
+pub fn pretty_print_item(item: &Item) -> String {
+    let mut printer = Printer::default();
+    printer.print_item(item);
+    printer.out.truncate(printer.out.trim_end().len());
+    printer.out
+}
+
+/// Like [`pretty_print_item`], but keeps the comments inside of the item, like
+/// [`pretty_print_ast_with_comments`].
+pub fn pretty_print_item_with_comments(item: &Item, text: &str) -> String {
+    let span = item.span();
+    let mut comments = comments(text);
+    comments.retain(|comment| span.start <= comment.span.start && comment.span.end <= span.end);
+    let mut printer = Printer::with_comments(comments);
+    printer.print_item(item);
+    printer.out.truncate(printer.out.trim_end().len());
+    printer.out
+}
+
+/// Statements that contain blocks span multiple lines.
+pub fn pretty_print_stmt(stmt: &Stmt) -> String {
+    let mut printer = Printer::default();
+    printer.print_stmt(stmt);
+    printer.out
+}
+
+pub fn pretty_print_expr(expr: &Expr) -> String {
+    let mut printer = Printer::default();
+    printer.print_expr(expr);
+    printer.out
+}
+
+pub fn pretty_print_ty(ty: &Ty) -> String {
+    let mut printer = Printer::default();
+    printer.print_ty(ty);
+    printer.out
+}
 
+/// The function without its body, on a single line.
+pub fn fn_signature(fn_decl: &FnDecl) -> String {
+    let mut printer = Printer::default();
+    printer.word("fn ");
+    printer.word(&fn_decl.name);
+    printer.word("(");
+    printer.print_name_tys_inline(&fn_decl.params);
+    printer.word(")");
+    if let Some(ret_ty) = &fn_decl.ret_ty {
+        printer.word(" -> ");
+        printer.print_ty(ret_ty);
+    }
     printer.out
 }
 
+/// The struct without doc comments, on a single line.
+pub fn struct_signature(struct_decl: &StructDecl) -> String {
+    let mut printer = Printer::default();
+    printer.word("struct ");
+    printer.word(&struct_decl.name);
+    printer.word(" { ");
+    printer.print_name_tys_inline(&struct_decl.fields);
+    printer.word(" }");
+    printer.out
+}
+
+/// A comment that the parser skips.
+struct Comment {
+    span: Span,
+    text: String,
+    /// Whether there is only whitespace in front of it on its line.
+    own_line: bool,
+}
+
+/// The normal comments and the stray doc comments in `text`, in order.
+fn comments(text: &str) -> Vec<Comment> {
+    let tokens = lexer::lex(text).spanned().collect::<Vec<_>>();
+    let stray = parser::stray_doc_comments(&tokens);
+    let comment = |span: Span| {
+        let line_start = text[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        Comment {
+            text: text[span.clone()].trim_end().to_string(),
+            own_line: text[line_start..span.start].trim().is_empty(),
+            span,
+        }
+    };
+
+    let mut comments = Vec::new();
+    // Between tokens, there is only whitespace and normal comments, which go to the end of the line.
+    let gap_comments = |gap: Span, comments: &mut Vec<Comment>| {
+        let mut start = gap.start;
+        while let Some(i) = text[start..gap.end].find("//") {
+            start += i;
+            let end = text[start..gap.end]
+                .find('\n')
+                .map_or(gap.end, |i| start + i);
+            comments.push(comment(start..end));
+            start = end;
+        }
+    };
+    let mut end = 0;
+    for ((_, span), stray) in tokens.iter().zip(stray) {
+        gap_comments(end..span.start, &mut comments);
+        if stray {
+            comments.push(comment(span.clone()));
+        }
+        end = span.end;
+    }
+    gap_comments(end..text.len(), &mut comments);
+    comments
+}
+
+#[derive(Default)]
 struct Printer {
     out: String,
     indent: usize,
+    /// The comments that still have to be printed, in reverse order.
+    comments: Vec<Comment>,
 }
+
 impl Printer {
+    fn with_comments(mut comments: Vec<Comment>) -> Self {
+        comments.reverse();
+        Printer {
+            comments,
+            ..Printer::default()
+        }
+    }
+
     fn print_items(&mut self, items: &[Item]) {
-        for item in items {
+        for (i, item) in items.iter().enumerate() {
+            self.leading_comments(item.span().start);
             self.print_item(item);
+            let next = items
+                .get(i + 1)
+                .map_or(usize::MAX, |item| item.span().start);
+            self.trailing_comment(next);
+            self.linebreak();
         }
+        self.leading_comments(usize::MAX);
     }
 
     fn print_item(&mut self, item: &Item) {
         match item {
             Item::FnDecl(fn_decl) => {
+                self.print_doc(&fn_decl.doc);
                 self.word("fn ");
                 self.word(&fn_decl.name);
                 self.word("(");
                 let params = &fn_decl.params;
-                if params.len() > 0 {
-                    self.linebreak_indent();
-                    for i in 0..params.len().saturating_sub(1) {
-                        let param = &fn_decl.params[i];
-                        self.print_name_ty(param);
-                        self.word(",");
+                if !params.is_empty() {
+                    self.indent += 1;
+                    for (i, param) in params.iter().enumerate() {
                         self.linebreak();
-                    }
-                    if params.len() > 0 {
-                        self.print_name_ty(params.last().unwrap());
+                        self.leading_comments(param.span.start);
+                        self.print_name_ty(param);
+                        match params.get(i + 1) {
+                            Some(next) => {
+                                self.word(",");
+                                self.trailing_comment(next.span.start);
+                            }
+                            None => self.trailing_comment(fn_decl.span.end),
+                        }
                     }
                     self.linebreak_unindent();
                 }
@@ -54,23 +198,76 @@ impl Printer {
                     self.print_ty(ret_ty);
                     self.word(" ");
                 }
-                self.print_block(&fn_decl.body);
-                self.linebreak();
+                self.print_block(&fn_decl.body, fn_decl.span.end);
+            }
+            Item::StructDecl(struct_decl) => {
+                self.print_doc(&struct_decl.doc);
+                self.word("struct ");
+                self.word(&struct_decl.name);
+                self.word(" {");
+                let (fields, end) = (&struct_decl.fields, struct_decl.span.end);
+                if !fields.is_empty() || self.comment_before(end) {
+                    self.indent += 1;
+                    for (i, field) in fields.iter().enumerate() {
+                        self.linebreak();
+                        self.leading_comments(field.span.start);
+                        self.print_name_ty(field);
+                        match fields.get(i + 1) {
+                            Some(next) => {
+                                self.word(",");
+                                self.trailing_comment(next.span.start);
+                            }
+                            None => self.trailing_comment(end),
+                        }
+                    }
+                    self.remaining_comments(end);
+                    self.linebreak_unindent();
+                }
+                self.word("}");
             }
-            Item::StructDecl(_) => {
-                todo!()
+        }
+    }
+
+    /// Leaves the cursor at the start of the line after the comment.
+    fn print_doc(&mut self, doc: &Option<String>) {
+        if let Some(doc) = doc {
+            // Not `lines`, which would drop a trailing empty line.
+            for line in doc.split('\n') {
+                self.word("///");
+                if !line.is_empty() {
+                    self.word(" ");
+                    self.word(line);
+                }
+                self.linebreak();
             }
         }
     }
 
     fn print_name_ty(&mut self, name_ty: &NameTyPair) {
+        self.print_doc(&name_ty.doc);
         self.word(&name_ty.name);
         self.word(": ");
         self.print_ty(&name_ty.ty);
     }
 
+    /// Doesn't print doc comments, since they need their own lines.
+    fn print_name_tys_inline(&mut self, name_tys: &[NameTyPair]) {
+        if let [first, rest @ ..] = name_tys {
+            self.word(&first.name);
+            self.word(": ");
+            self.print_ty(&first.ty);
+            for name_ty in rest {
+                self.word(", ");
+                self.word(&name_ty.name);
+                self.word(": ");
+                self.print_ty(&name_ty.ty);
+            }
+        }
+    }
+
     fn print_ty(&mut self, ty: &Ty) {
         match &ty.kind {
+            TyKind::Bool => self.word("bool"),
             TyKind::Name(name) => self.word(name),
             TyKind::Ptr(ty) => {
                 self.word("ptr ");
@@ -80,17 +277,19 @@ impl Printer {
     }
 
     /// Starts off after the `{`. Indents itself first if necessary. Stops at the place where `}` should be inserted.
-    fn print_block(&mut self, stmts: &[Stmt]) {
+    /// `end` is where the block ends in the source, so that the comments at its end stay in it.
+    fn print_block(&mut self, stmts: &[Stmt], end: usize) {
         self.word("{");
-        if let [first, rest @ ..] = &stmts {
-            self.linebreak_indent();
-            self.print_stmt(first);
-
-            for stmt in rest {
+        if !stmts.is_empty() || self.comment_before(end) {
+            self.indent += 1;
+            for (i, stmt) in stmts.iter().enumerate() {
                 self.linebreak();
+                self.leading_comments(stmt.span().start);
                 self.print_stmt(stmt);
+                let next = stmts.get(i + 1).map_or(end, |stmt| stmt.span().start);
+                self.trailing_comment(next);
             }
-
+            self.remaining_comments(end);
             self.linebreak_unindent();
         }
         self.word("}");
@@ -124,14 +323,26 @@ impl Printer {
             Stmt::WhileStmt(while_stmt) => {
                 self.word("while ");
                 self.print_expr(&while_stmt.cond);
-                self.print_block(&while_stmt.body);
+                self.word(" ");
+                self.print_block(&while_stmt.body, while_stmt.span.end);
             }
             Stmt::LoopStmt(loop_stmt) => {
                 self.word("loop ");
-                self.print_block(&loop_stmt.body);
+                self.print_block(&loop_stmt.body, loop_stmt.span.end);
+            }
+            Stmt::Return(return_stmt) => {
+                self.word("return");
+                if let Some(expr) = &return_stmt.expr {
+                    self.word(" ");
+                    self.print_expr(expr);
+                }
+                self.word(";");
             }
+            Stmt::Break(_) => self.word("break;"),
+            Stmt::Continue(_) => self.word("continue;"),
             Stmt::Item(item) => {
                 self.print_item(item);
+                self.linebreak();
             }
             Stmt::Expr(expr) => {
                 self.print_expr(expr);
@@ -144,12 +355,17 @@ impl Printer {
         self.word("if ");
         self.print_expr(&if_stmt.cond);
         self.word(" ");
-        self.print_block(&if_stmt.body);
+        let body_end = match &if_stmt.else_part {
+            Some(ElsePart::Else(_, span)) => span.start,
+            Some(ElsePart::ElseIf(else_if)) => else_if.span.start,
+            None => if_stmt.span.end,
+        };
+        self.print_block(&if_stmt.body, body_end);
         if let Some(else_part) = &if_stmt.else_part {
             self.word(" else ");
             match else_part {
-                ElsePart::Else(stmts, _) => {
-                    self.print_block(stmts);
+                ElsePart::Else(stmts, span) => {
+                    self.print_block(stmts, span.end);
                 }
                 ElsePart::ElseIf(if_stmt) => {
                     self.print_if(if_stmt);
@@ -163,7 +379,7 @@ impl Printer {
             ExprKind::BinOp(bin_op) => {
                 self.print_expr_wrapped(&bin_op.lhs);
                 self.word(match bin_op.kind {
-                    BinOpKind::Eq => "  == ",
+                    BinOpKind::Eq => " == ",
                     BinOpKind::Neq => " != ",
                     BinOpKind::Gt => " > ",
                     BinOpKind::Lt => " < ",
@@ -173,7 +389,7 @@ impl Printer {
                     BinOpKind::Sub => " - ",
                     BinOpKind::Mul => " * ",
                     BinOpKind::Div => " / ",
-                    BinOpKind::Mod => " % ",
+                    BinOpKind::Rem => " % ",
                     BinOpKind::Shr => " >> ",
                     BinOpKind::Shl => " << ",
                     BinOpKind::And => " && ",
@@ -199,7 +415,7 @@ impl Printer {
                 self.word(&field_access.field_name);
             }
             ExprKind::Call(call) => {
-                self.print_expr(&call.callee);
+                self.print_expr_wrapped(&call.callee);
                 self.word("(");
                 if let [first, rest @ ..] = &*call.args {
                     self.print_expr(first);
@@ -212,6 +428,7 @@ impl Printer {
             }
             ExprKind::Literal(literal) => match literal {
                 Literal::Integer(int, _) => write!(self.out, "{int}").unwrap(),
+                Literal::Bool(bool, _) => write!(self.out, "{bool}").unwrap(),
                 Literal::String(string, _) => {
                     self.word("\"");
                     // FIXME: Handle escapes.
@@ -255,6 +472,40 @@ impl Printer {
 
     // utility functions
 
+    fn comment_before(&self, offset: usize) -> bool {
+        matches!(self.comments.last(), Some(comment) if comment.span.start < offset)
+    }
+
+    /// Prints the comments in front of `offset` on their own lines. Starts and stops at the start
+    /// of a line.
+    fn leading_comments(&mut self, offset: usize) {
+        while self.comment_before(offset) {
+            let comment = self.comments.pop().unwrap();
+            self.word(&comment.text);
+            self.linebreak();
+        }
+    }
+
+    /// Prints the comment behind the code that was just printed if it was on the same line and
+    /// is in front of `offset`. Has to be followed by a line break.
+    fn trailing_comment(&mut self, offset: usize) {
+        if self.comment_before(offset) && !self.comments.last().unwrap().own_line {
+            let comment = self.comments.pop().unwrap();
+            self.word(" ");
+            self.word(&comment.text);
+        }
+    }
+
+    /// Prints the comments in front of `offset` on their own lines after the code that was just
+    /// printed.
+    fn remaining_comments(&mut self, offset: usize) {
+        while self.comment_before(offset) {
+            let comment = self.comments.pop().unwrap();
+            self.linebreak();
+            self.word(&comment.text);
+        }
+    }
+
     fn word(&mut self, word: &str) {
         self.out.push_str(word);
     }
@@ -274,3 +525,72 @@ impl Printer {
         self.word(&"    ".repeat(self.indent))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{pretty_print_ast_with_comments, pretty_print_item_with_comments};
+    use crate::{parser, Database, SourceProgram};
+
+    fn format(src: &str) -> String {
+        let db = Database::default();
+        let source = SourceProgram::new(&db, src.to_owned(), "uwu.ub".into());
+        let file = parser::parse(&db, source).expect("no file");
+        pretty_print_ast_with_comments(&file, src)
+    }
+
+    const COMMENTS: &str = "// File header.
+//// Banner.
+/// Docs.
+fn main(a: u64, // first
+    // before b
+    b: u64) {
+    // leading
+    let x = a + b; // trailing
+    /// stray doc
+    if x == 1 {
+        // only a comment
+    } else {
+        x = 2; // in else
+        // end of else
+    }
+    foo(1, // inside a call
+        2);
+    // end of body
+}
+struct S {
+    /// Field docs.
+    x: u64, // after x
+    y: u64 // after y
+    // end of struct
+}
+struct E { // empty
+}
+// End of file.
+";
+
+    #[test]
+    fn comments() {
+        let formatted = format(COMMENTS);
+        insta::assert_snapshot!(formatted);
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    fn comments_without_code() {
+        assert_eq!(format("// nothing here\n"), "// nothing here\n");
+        assert_eq!(format(""), "");
+    }
+
+    #[test]
+    fn item_comments() {
+        let src = "// before\nfn f() { g(); // call\n} // after\n";
+        let db = Database::default();
+        let source = SourceProgram::new(&db, src.to_owned(), "uwu.ub".into());
+        let file = parser::parse(&db, source).expect("no file");
+        // Only the comments inside of the item belong to it.
+        assert_eq!(
+            pretty_print_item_with_comments(&file.items[0], src),
+            "fn f() {\n    g(); // call\n}"
+        );
+    }
+}
@@ -0,0 +1,766 @@
+//! Name resolution. Links every name in a file to the function, struct, parameter or local
+//! variable it refers to. Files are resolved on their own since there are no imports.
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{ElsePart, Expr, ExprKind, File, IfStmt, Item, Stmt, Ty, TyKind},
+    parser::{parse, Span},
+    Db, SourceProgram,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DefId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    Fn,
+    Struct,
+    Field,
+    Param,
+    Local,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Def {
+    pub kind: DefKind,
+    pub name: String,
+    pub name_span: Span,
+    /// The whole declaration.
+    pub span: Span,
+    /// The declared type of fields, parameters and locals.
+    pub ty: Option<Ty>,
+    /// Where parameters, locals and functions declared in blocks can be used. Other items are
+    /// visible in the whole file.
+    pub visible: Option<Span>,
+}
+
+/// A name that was resolved to a definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub span: Span,
+    pub def: DefId,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Resolutions {
+    defs: Vec<Def>,
+    /// In source order. Names that couldn't be resolved are left out.
+    references: Vec<Reference>,
+}
+
+/// The names that refer to each definition, in source order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct References {
+    by_def: HashMap<DefId, Vec<Span>>,
+}
+
+#[salsa::tracked(return_ref)]
+pub fn resolve(db: &dyn Db, source: SourceProgram) -> Resolutions {
+    match parse(db, source) {
+        Some(file) => resolve_file(&file),
+        None => Resolutions::default(),
+    }
+}
+
+/// Resolves a file that doesn't have to come from source text, like one from
+/// [`crate::ast::build`].
+pub fn resolve_file(file: &File) -> Resolutions {
+    let mut resolver = Resolver::default();
+    resolver.file(file);
+    resolver.resolutions
+}
+
+#[salsa::tracked(return_ref)]
+pub fn references(db: &dyn Db, source: SourceProgram) -> References {
+    let mut by_def = HashMap::<_, Vec<_>>::new();
+    for reference in resolve(db, source).references() {
+        by_def
+            .entry(reference.def)
+            .or_default()
+            .push(reference.span.clone());
+    }
+    References { by_def }
+}
+
+impl References {
+    /// Doesn't include the name of the definition itself.
+    pub fn of(&self, def: DefId) -> &[Span] {
+        self.by_def.get(&def).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+impl Resolutions {
+    pub fn def(&self, id: DefId) -> &Def {
+        &self.defs[id.0]
+    }
+
+    pub fn defs(&self) -> impl Iterator<Item = (DefId, &Def)> {
+        self.defs.iter().enumerate().map(|(i, def)| (DefId(i), def))
+    }
+
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    /// Every resolved name and the name of every definition, sorted by their start. The flag is
+    /// set for the names of definitions.
+    pub fn names(&self) -> Vec<(Span, DefId, bool)> {
+        let mut names = self
+            .references
+            .iter()
+            .map(|reference| (reference.span.clone(), reference.def, false))
+            .chain(
+                self.defs()
+                    .map(|(id, def)| (def.name_span.clone(), id, true)),
+            )
+            .collect::<Vec<_>>();
+        names.sort_by_key(|(span, ..)| span.start);
+        names
+    }
+
+    /// The functions, structs, parameters and locals that can be named at `offset`. Shadowed
+    /// definitions are included too.
+    pub fn visible_at(&self, offset: usize) -> impl Iterator<Item = (DefId, &Def)> {
+        let contains = |span: &Span, offset: usize| span.start <= offset && offset <= span.end;
+        self.defs().filter(move |(_, def)| match def.kind {
+            DefKind::Struct => true,
+            DefKind::Field => false,
+            DefKind::Fn => match &def.visible {
+                Some(span) => contains(span, offset),
+                None => true,
+            },
+            DefKind::Param | DefKind::Local => {
+                matches!(&def.visible, Some(span) if contains(span, offset))
+                    // Functions in between don't see the locals of the functions around them.
+                    && !self.defs().any(|(_, other)| {
+                        other.kind == DefKind::Fn
+                            && contains(&other.span, offset)
+                            && !contains(&other.span, def.span.start)
+                    })
+            }
+        })
+    }
+
+    pub fn struct_named(&self, name: &str) -> Option<DefId> {
+        self.defs()
+            .find(|(_, def)| def.kind == DefKind::Struct && def.name == name)
+            .map(|(id, _)| id)
+    }
+
+    pub fn fields(&self, struct_id: DefId) -> impl Iterator<Item = (DefId, &Def)> {
+        let struct_span = self.def(struct_id).span.clone();
+        self.defs().filter(move |(_, def)| {
+            def.kind == DefKind::Field
+                && struct_span.start <= def.span.start
+                && def.span.end <= struct_span.end
+        })
+    }
+
+    /// Another definition that would clash with `def` if `def` was named `name`: a struct or
+    /// field of the same name, or a function, parameter or local that could shadow it or be
+    /// shadowed by it.
+    pub fn conflict(&self, def: DefId, name: &str) -> Option<DefId> {
+        let renamed = self.def(def);
+        let contains =
+            |outer: &Span, inner: &Span| outer.start <= inner.start && inner.end <= outer.end;
+        let clashes = |other: &Def| match (renamed.kind, other.kind) {
+            (DefKind::Struct, DefKind::Struct) => true,
+            (DefKind::Field, DefKind::Field) => self.defs().any(|(_, def)| {
+                def.kind == DefKind::Struct
+                    && contains(&def.span, &renamed.span)
+                    && contains(&def.span, &other.span)
+            }),
+            (
+                DefKind::Fn | DefKind::Param | DefKind::Local,
+                DefKind::Fn | DefKind::Param | DefKind::Local,
+            ) => match (&renamed.visible, &other.visible) {
+                (Some(a), Some(b)) => a.start < b.end && b.start < a.end,
+                // Functions are visible everywhere.
+                _ => true,
+            },
+            _ => false,
+        };
+        self.defs()
+            .find(|&(id, other)| id != def && other.name == name && clashes(other))
+            .map(|(id, _)| id)
+    }
+
+    /// The definition that the name with exactly this span refers to, if it could be resolved.
+    pub fn resolved(&self, span: &Span) -> Option<DefId> {
+        let i = self
+            .references
+            .binary_search_by_key(&span.start, |reference| reference.span.start)
+            .ok()?;
+        let reference = &self.references[i];
+        (reference.span == *span).then_some(reference.def)
+    }
+
+    /// The definition that the name at `offset` refers to. If `offset` is on the name of a
+    /// definition, that definition is returned. A name also counts as being at its end offset so
+    /// that a cursor right behind it finds it.
+    pub fn def_at(&self, offset: usize) -> Option<DefId> {
+        self.name_at(offset).map(|(_, def)| def)
+    }
+
+    /// Like [`Resolutions::def_at`], but also returns the span of the name at `offset`.
+    pub fn name_at(&self, offset: usize) -> Option<(Span, DefId)> {
+        let contains = |span: &Span| span.start <= offset && offset <= span.end;
+        self.references
+            .iter()
+            .find(|reference| contains(&reference.span))
+            .map(|reference| (reference.span.clone(), reference.def))
+            .or_else(|| {
+                self.defs()
+                    .find(|(_, def)| contains(&def.name_span))
+                    .map(|(id, def)| (def.name_span.clone(), id))
+            })
+    }
+}
+
+#[derive(Default)]
+struct Resolver {
+    resolutions: Resolutions,
+    /// Structs live in their own namespace, so a struct and a function can share a name.
+    types: HashMap<String, DefId>,
+    /// The innermost scope is last. The outermost scope contains the functions of the file.
+    scopes: Vec<Scope>,
+}
+
+struct Scope {
+    names: Vec<(String, DefId)>,
+    /// The offset at which the scope ends.
+    end: usize,
+    /// Whether this is the scope of the parameters of a function. Parameters and locals outside
+    /// of it can't be used in the function.
+    function: bool,
+}
+
+impl Scope {
+    fn new(end: usize) -> Self {
+        Self {
+            names: Vec::new(),
+            end,
+            function: false,
+        }
+    }
+
+    fn function(end: usize) -> Self {
+        Self {
+            function: true,
+            ..Self::new(end)
+        }
+    }
+}
+
+impl Resolver {
+    fn file(&mut self, file: &File) {
+        self.scopes.push(Scope::new(usize::MAX));
+        // Items can be used before they are declared.
+        for item in &file.items {
+            self.declare_item(item);
+        }
+        for item in &file.items {
+            self.item(item);
+        }
+        self.scopes.pop();
+    }
+
+    fn define(
+        &mut self,
+        kind: DefKind,
+        name: &str,
+        name_span: &Span,
+        span: &Span,
+        ty: Option<&Ty>,
+    ) -> DefId {
+        let id = DefId(self.resolutions.defs.len());
+        self.resolutions.defs.push(Def {
+            kind,
+            name: name.to_owned(),
+            name_span: name_span.clone(),
+            span: span.clone(),
+            ty: ty.cloned(),
+            visible: None,
+        });
+        id
+    }
+
+    /// Defines a value and makes it visible in the current scope.
+    fn define_value(
+        &mut self,
+        kind: DefKind,
+        name: &str,
+        name_span: &Span,
+        span: &Span,
+        ty: Option<&Ty>,
+    ) {
+        let id = self.define(kind, name, name_span, span, ty);
+        let in_block = self.scopes.len() > 1;
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("no scope to define a value in");
+        match kind {
+            DefKind::Param | DefKind::Local => {
+                self.resolutions.defs[id.0].visible = Some(span.end..scope.end);
+            }
+            // A function in a block can call itself, but is gone when the block ends.
+            DefKind::Fn if in_block => {
+                self.resolutions.defs[id.0].visible = Some(span.start..scope.end);
+            }
+            _ => {}
+        }
+        scope.names.push((name.to_owned(), id));
+    }
+
+    fn lookup_value(&self, name: &str) -> Option<DefId> {
+        // Only functions can be used from the functions declared in their scope.
+        let mut only_functions = false;
+        for scope in self.scopes.iter().rev() {
+            let found = scope.names.iter().rev().find(|(def_name, id)| {
+                def_name == name
+                    && (!only_functions || self.resolutions.def(*id).kind == DefKind::Fn)
+            });
+            if let Some((_, id)) = found {
+                return Some(*id);
+            }
+            only_functions |= scope.function;
+        }
+        None
+    }
+
+    fn reference(&mut self, span: &Span, def: Option<DefId>) {
+        if let Some(def) = def {
+            self.resolutions.references.push(Reference {
+                span: span.clone(),
+                def,
+            });
+        }
+    }
+
+    fn declare_item(&mut self, item: &Item) {
+        match item {
+            Item::FnDecl(fn_decl) => self.define_value(
+                DefKind::Fn,
+                &fn_decl.name,
+                &fn_decl.name_span,
+                &fn_decl.span,
+                None,
+            ),
+            Item::StructDecl(struct_decl) => {
+                let id = self.define(
+                    DefKind::Struct,
+                    &struct_decl.name,
+                    &struct_decl.name_span,
+                    &struct_decl.span,
+                    None,
+                );
+                self.types.entry(struct_decl.name.clone()).or_insert(id);
+            }
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::FnDecl(fn_decl) => {
+                self.scopes.push(Scope::function(fn_decl.span.end));
+                for param in &fn_decl.params {
+                    self.ty(&param.ty);
+                    self.define_value(
+                        DefKind::Param,
+                        &param.name,
+                        &param.name_span,
+                        &param.span,
+                        Some(&param.ty),
+                    );
+                }
+                if let Some(ret_ty) = &fn_decl.ret_ty {
+                    self.ty(ret_ty);
+                }
+                self.block(&fn_decl.body, fn_decl.span.end);
+                self.scopes.pop();
+            }
+            Item::StructDecl(struct_decl) => {
+                for field in &struct_decl.fields {
+                    self.ty(&field.ty);
+                    self.define(
+                        DefKind::Field,
+                        &field.name,
+                        &field.name_span,
+                        &field.span,
+                        Some(&field.ty),
+                    );
+                }
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &Ty) {
+        match &ty.kind {
+            TyKind::Bool => {}
+            TyKind::Ptr(inner) => self.ty(inner),
+            TyKind::Name(name) => {
+                let def = self.types.get(name).copied();
+                self.reference(&ty.span, def);
+            }
+        }
+    }
+
+    /// Blocks don't have spans, so `end` is the end of the construct that contains the block.
+    fn block(&mut self, stmts: &[Stmt], end: usize) {
+        self.scopes.push(Scope::new(end));
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::VarDecl(decl) => {
+                if let Some(ty) = &decl.ty {
+                    self.ty(ty);
+                }
+                // The variable is only visible after its initializer.
+                if let Some(rhs) = &decl.rhs {
+                    self.expr(rhs);
+                }
+                self.define_value(
+                    DefKind::Local,
+                    &decl.name,
+                    &decl.name_span,
+                    &decl.span,
+                    decl.ty.as_ref(),
+                );
+            }
+            Stmt::Assignment(assign) => {
+                self.expr(&assign.place);
+                self.expr(&assign.rhs);
+            }
+            Stmt::IfStmt(if_stmt) => self.if_stmt(if_stmt),
+            Stmt::WhileStmt(while_stmt) => {
+                self.expr(&while_stmt.cond);
+                self.block(&while_stmt.body, while_stmt.span.end);
+            }
+            Stmt::LoopStmt(loop_stmt) => self.block(&loop_stmt.body, loop_stmt.span.end),
+            Stmt::Return(return_stmt) => {
+                if let Some(expr) = &return_stmt.expr {
+                    self.expr(expr);
+                }
+            }
+            Stmt::Break(_) | Stmt::Continue(_) => {}
+            Stmt::Item(item) => {
+                self.declare_item(item);
+                self.item(item);
+            }
+            Stmt::Expr(expr) => self.expr(expr),
+        }
+    }
+
+    fn if_stmt(&mut self, if_stmt: &IfStmt) {
+        self.expr(&if_stmt.cond);
+        // The locals of the body end where the else part starts.
+        let body_end = match &if_stmt.else_part {
+            Some(ElsePart::Else(_, span)) => span.start,
+            Some(ElsePart::ElseIf(else_if)) => else_if.span.start,
+            None => if_stmt.span.end,
+        };
+        self.block(&if_stmt.body, body_end);
+        match &if_stmt.else_part {
+            Some(ElsePart::Else(stmts, span)) => self.block(stmts, span.end),
+            Some(ElsePart::ElseIf(if_stmt)) => self.if_stmt(if_stmt),
+            None => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::BinOp(bin_op) => {
+                self.expr(&bin_op.lhs);
+                self.expr(&bin_op.rhs);
+            }
+            ExprKind::UnaryOp(unary_op) => self.expr(&unary_op.expr),
+            ExprKind::FieldAccess(field_access) => self.expr(&field_access.expr),
+            ExprKind::Call(call) => {
+                self.expr(&call.callee);
+                for arg in &call.args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Array(exprs) => {
+                for expr in exprs {
+                    self.expr(expr);
+                }
+            }
+            ExprKind::Name(name) => {
+                let def = self.lookup_value(name);
+                self.reference(&expr.span, def);
+            }
+            ExprKind::Literal(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve, resolve_file, DefId, DefKind, Resolutions};
+    use crate::{ast::build::Builder, Database, SourceProgram};
+
+    fn resolve_src(src: &str) -> Resolutions {
+        let db = Database::default();
+        let source = SourceProgram::new(&db, src.to_owned(), "uwu.ub".into());
+        resolve(&db, source).clone()
+    }
+
+    /// The offset of the `n`th occurrence of `pattern`, counting from zero.
+    fn at(src: &str, pattern: &str, n: usize) -> usize {
+        src.match_indices(pattern)
+            .nth(n)
+            .unwrap_or_else(|| panic!("no {n}th `{pattern}`"))
+            .0
+    }
+
+    fn def_named(resolutions: &Resolutions, kind: DefKind, name: &str) -> DefId {
+        let mut defs = resolutions
+            .defs()
+            .filter(|(_, def)| def.kind == kind && def.name == name);
+        let (id, _) = defs.next().expect("no such definition");
+        assert!(defs.next().is_none(), "more than one `{name}`");
+        id
+    }
+
+    /// Where the definition that the name at `offset` refers to is declared.
+    fn declared_at(resolutions: &Resolutions, offset: usize) -> Option<usize> {
+        let def = resolutions.def_at(offset)?;
+        Some(resolutions.def(def).name_span.start)
+    }
+
+    #[test]
+    fn shadowing() {
+        let src = "fn f(xx: u64) { xx; let xx = 1; xx; let xx = xx; xx; }";
+        let r = resolve_src(src);
+        assert_eq!(declared_at(&r, at(src, "xx", 1)), Some(at(src, "xx", 0)));
+        assert_eq!(declared_at(&r, at(src, "xx", 3)), Some(at(src, "xx", 2)));
+        assert_eq!(declared_at(&r, at(src, "xx", 6)), Some(at(src, "xx", 4)));
+    }
+
+    #[test]
+    fn initializer_refers_to_the_previous_binding() {
+        let src = "fn f() { let yy = 1; let yy = yy + 1; let zz = zz; }";
+        let r = resolve_src(src);
+        assert_eq!(declared_at(&r, at(src, "yy", 2)), Some(at(src, "yy", 0)));
+        // There is nothing for `zz` to refer to yet.
+        assert_eq!(r.references().len(), 1);
+    }
+
+    #[test]
+    fn forward_references_to_items() {
+        let src = "fn first(p: ptr Later) { second(); } fn second() {} struct Later { x: u64 }";
+        let r = resolve_src(src);
+        assert_eq!(
+            declared_at(&r, at(src, "Later", 0)),
+            Some(at(src, "Later", 1))
+        );
+        assert_eq!(
+            declared_at(&r, at(src, "second", 0)),
+            Some(at(src, "second", 1))
+        );
+    }
+
+    #[test]
+    fn locals_end_with_their_block() {
+        let src = "fn f() { if 1 { let aa = 1; } else { aa; } while 1 { let bb = 1; } bb; }";
+        let r = resolve_src(src);
+        assert!(r.references().is_empty());
+
+        let aa = r.def_at(at(src, "aa", 0)).unwrap();
+        let visible = r.def(aa).visible.clone().unwrap();
+        assert!(visible.end <= at(src, "else", 0) + "else ".len());
+        assert!(r
+            .visible_at(at(src, "aa", 1))
+            .all(|(_, def)| def.name != "aa"));
+    }
+
+    #[test]
+    fn structs_and_functions_have_separate_namespaces() {
+        let src = "struct same { x: u64 } fn same(s: same) { same(); }";
+        let r = resolve_src(src);
+        let kind = |n| r.def(r.def_at(at(src, "same", n)).unwrap()).kind;
+        assert_eq!(kind(0), DefKind::Struct);
+        assert_eq!(kind(1), DefKind::Fn);
+        assert_eq!(kind(2), DefKind::Struct);
+        assert_eq!(kind(3), DefKind::Fn);
+    }
+
+    #[test]
+    fn conflicts() {
+        let src = "struct Sa { fx: u64, fy: u64 } struct Sb { fz: u64 } \
+                   fn fun(par: u64) { let loc = par; } fn gun(qar: u64) {}";
+        let r = resolve_src(src);
+        let def = |name: &str| r.def_at(at(src, name, 0)).unwrap();
+
+        assert_eq!(r.conflict(def("Sa"), "Sb"), Some(def("Sb")));
+        assert_eq!(r.conflict(def("Sa"), "Sc"), None);
+        // Structs don't clash with values.
+        assert_eq!(r.conflict(def("Sa"), "fun"), None);
+
+        assert_eq!(r.conflict(def("fx"), "fy"), Some(def("fy")));
+        // Fields of other structs are fine.
+        assert_eq!(r.conflict(def("fx"), "fz"), None);
+
+        // Functions are visible everywhere.
+        assert_eq!(r.conflict(def("loc"), "gun"), Some(def("gun")));
+        assert_eq!(r.conflict(def("gun"), "par"), Some(def("par")));
+        assert_eq!(r.conflict(def("loc"), "par"), Some(def("par")));
+        // `qar` is in another function.
+        assert_eq!(r.conflict(def("par"), "qar"), None);
+        // Renaming to its own name isn't a conflict.
+        assert_eq!(r.conflict(def("par"), "par"), None);
+    }
+
+    // Nested functions can't be parsed yet, so these trees are built directly.
+
+    #[test]
+    fn nested_function_is_visible_after_its_declaration() {
+        let b = Builder::default();
+        let before = b.name("inner");
+        let before_span = before.span.clone();
+        let inner = b.fn_("inner", Vec::new(), None, Vec::new());
+        let after = b.name("inner");
+        let after_span = after.span.clone();
+        let outer = b.fn_(
+            "outer",
+            Vec::new(),
+            None,
+            vec![
+                b.expr(b.call(before, Vec::new())),
+                b.item(inner),
+                b.expr(b.call(after, Vec::new())),
+            ],
+        );
+        let r = resolve_file(&b.file("uwu.ub", vec![outer]));
+
+        assert_eq!(r.resolved(&before_span), None);
+        let inner = def_named(&r, DefKind::Fn, "inner");
+        assert_eq!(r.resolved(&after_span), Some(inner));
+    }
+
+    #[test]
+    fn nested_function_parameters_shadow_outer_locals() {
+        let b = Builder::default();
+        let outer_x = b.let_("x", None, Some(b.int(1)));
+        let in_inner = b.name("x");
+        let in_inner_span = in_inner.span.clone();
+        let inner = b.fn_(
+            "inner",
+            vec![b.param("x", b.ty_bool())],
+            None,
+            vec![b.expr(in_inner)],
+        );
+        let in_outer = b.name("x");
+        let in_outer_span = in_outer.span.clone();
+        let outer = b.fn_(
+            "outer",
+            Vec::new(),
+            None,
+            vec![outer_x, b.item(inner), b.expr(in_outer)],
+        );
+        let r = resolve_file(&b.file("uwu.ub", vec![outer]));
+
+        let param = def_named(&r, DefKind::Param, "x");
+        let local = def_named(&r, DefKind::Local, "x");
+        assert_eq!(r.resolved(&in_inner_span), Some(param));
+        assert_eq!(r.resolved(&in_outer_span), Some(local));
+    }
+
+    #[test]
+    fn nested_function_is_scoped_to_its_block() {
+        let b = Builder::default();
+        let inner = b.fn_("inner", Vec::new(), None, Vec::new());
+        let outside = b.name("inner");
+        let outside_span = outside.span.clone();
+        let outer = b.fn_(
+            "outer",
+            Vec::new(),
+            None,
+            vec![
+                b.loop_(vec![b.item(inner), b.break_()]),
+                b.expr(b.call(outside, Vec::new())),
+            ],
+        );
+        let r = resolve_file(&b.file("uwu.ub", vec![outer]));
+
+        assert_eq!(r.resolved(&outside_span), None);
+    }
+
+    #[test]
+    fn nested_function_is_visible_in_its_block() {
+        let b = Builder::default();
+        let inner = b.fn_("inner", Vec::new(), None, Vec::new());
+        let in_loop = b.name("x");
+        let in_loop_span = in_loop.span.clone();
+        let loop_ = b.loop_(vec![b.item(inner), b.expr(in_loop)]);
+        let outside = b.name("x");
+        let outside_span = outside.span.clone();
+        let outer = b.fn_("outer", Vec::new(), None, vec![loop_, b.expr(outside)]);
+        let r = resolve_file(&b.file("uwu.ub", vec![outer]));
+
+        let inner = def_named(&r, DefKind::Fn, "inner");
+        let outer = def_named(&r, DefKind::Fn, "outer");
+        let visible = r
+            .def(inner)
+            .visible
+            .clone()
+            .expect("visible in the whole file");
+        assert_eq!(visible.start, r.def(inner).span.start);
+        assert!(visible.end < r.def(outer).span.end);
+        let is_visible = |offset| r.visible_at(offset).any(|(id, _)| id == inner);
+        assert!(is_visible(in_loop_span.end));
+        assert!(!is_visible(outside_span.end));
+        // Functions of the file are still visible everywhere.
+        assert_eq!(r.conflict(inner, "outer"), Some(outer));
+    }
+
+    #[test]
+    fn nested_function_cant_use_outer_locals() {
+        let b = Builder::default();
+        let outer_x = b.let_("x", None, Some(b.int(1)));
+        let in_inner = b.name("x");
+        let in_inner_span = in_inner.span.clone();
+        let recursive = b.name("inner");
+        let recursive_span = recursive.span.clone();
+        let inner = b.fn_(
+            "inner",
+            Vec::new(),
+            None,
+            vec![b.expr(in_inner), b.expr(b.call(recursive, Vec::new()))],
+        );
+        let outer = b.fn_("outer", Vec::new(), None, vec![outer_x, b.item(inner)]);
+        let r = resolve_file(&b.file("uwu.ub", vec![outer]));
+
+        assert_eq!(r.resolved(&in_inner_span), None);
+        let local = def_named(&r, DefKind::Local, "x");
+        assert!(!r.visible_at(in_inner_span.start).any(|(id, _)| id == local));
+        // Functions around it still can be called.
+        let inner = def_named(&r, DefKind::Fn, "inner");
+        assert_eq!(r.resolved(&recursive_span), Some(inner));
+    }
+
+    #[test]
+    fn built_struct_types() {
+        let b = Builder::default();
+        let next_ty = b.ty_name("List");
+        let next_span = next_ty.span.clone();
+        let list = b.struct_("List", vec![b.param("next", b.ty_ptr(next_ty))]);
+        let r = resolve_file(&b.file("uwu.ub", vec![list]));
+
+        let list = def_named(&r, DefKind::Struct, "List");
+        assert_eq!(r.resolved(&next_span), Some(list));
+        let field = def_named(&r, DefKind::Field, "next");
+        assert_eq!(
+            r.fields(list).map(|(id, _)| id).collect::<Vec<_>>(),
+            [field]
+        );
+    }
+}
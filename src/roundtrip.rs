@@ -0,0 +1,423 @@
+//! Generators for random trees and a check that the pretty printer and the parser agree on them.
+//! Only trees that the parser can produce are generated, so the generators have to be extended
+//! together with the grammar.
+
+use proptest::{collection::vec, option, prelude::*};
+
+use crate::{
+    ast::{
+        Assignment, BinOp, BinOpKind, Call, ElsePart, Expr, ExprKind, File, FnDecl, IfStmt, Item,
+        Literal, NameTyPair, NodeId, ReturnStmt, Stmt, StructDecl, Ty, TyKind, UnaryOp,
+        UnaryOpKind, VarDecl, WhileStmt,
+    },
+    parser::{self, Span},
+    pretty::pretty_print_ast,
+    Database, Diagnostics, SourceProgram,
+};
+
+const KEYWORDS: &[&str] = &[
+    "struct", "fn", "if", "else", "while", "loop", "ptr", "let", "return", "break", "continue",
+    "true", "false",
+];
+
+/// Prints `file`, parses it again and asserts that the result is the same tree, apart from spans
+/// and node ids. Returns the parsed file.
+pub fn roundtrip(file: File) -> File {
+    let printed = pretty_print_ast(&file);
+    let db = Database::default();
+    let source = SourceProgram::new(&db, printed.clone(), file.name.clone());
+    let parsed = parser::parse(&db, source);
+    let errors = parser::parse::accumulated::<Diagnostics>(&db, source);
+    assert!(errors.is_empty(), "{printed}\n{errors:?}");
+    let parsed = parsed.expect("no errors but no file");
+
+    let mut expected = file;
+    let mut actual = parsed.clone();
+    erase_file(&mut expected);
+    erase_file(&mut actual);
+    assert_eq!(expected, actual, "{printed}");
+    parsed
+}
+
+fn name() -> impl Strategy<Value = String> {
+    // `bool` would be parsed back as the primitive type.
+    "[a-z_][a-z0-9_]{0,4}".prop_filter("keywords aren't names", |name| {
+        !KEYWORDS.contains(&name.as_str()) && name != "bool"
+    })
+}
+
+/// Lines of a doc comment. A single leading space would be removed by the parser.
+fn doc() -> impl Strategy<Value = Option<String>> {
+    option::of(vec("([a-z][a-z ]{0,8})?", 1..3).prop_map(|lines| lines.join("\n")))
+}
+
+fn span() -> Span {
+    0..0
+}
+
+fn id() -> NodeId {
+    NodeId::new(0)
+}
+
+fn expr(kind: ExprKind) -> Expr {
+    Expr {
+        kind,
+        id: id(),
+        span: span(),
+    }
+}
+
+fn name_ty_pair(name: String, ty: Ty, doc: Option<String>) -> NameTyPair {
+    NameTyPair {
+        name,
+        name_span: span(),
+        ty,
+        id: id(),
+        span: span(),
+        doc,
+    }
+}
+
+fn block() -> impl Strategy<Value = Vec<Stmt>> {
+    vec(any::<Stmt>(), 0..4)
+}
+
+impl Arbitrary for File {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        vec(any::<Item>(), 0..4)
+            .prop_map(|items| File {
+                name: "roundtrip.ub".into(),
+                items,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Item {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let param = (name(), any::<Ty>()).prop_map(|(name, ty)| name_ty_pair(name, ty, None));
+        let fn_decl = (
+            doc(),
+            name(),
+            vec(param, 0..3),
+            option::of(any::<Ty>()),
+            block(),
+        )
+            .prop_map(|(doc, name, params, ret_ty, body)| {
+                Item::FnDecl(FnDecl {
+                    name,
+                    name_span: span(),
+                    params,
+                    ret_ty,
+                    id: id(),
+                    span: span(),
+                    body,
+                    doc,
+                })
+            });
+
+        let field =
+            (doc(), name(), any::<Ty>()).prop_map(|(doc, name, ty)| name_ty_pair(name, ty, doc));
+        let struct_decl = (doc(), name(), vec(field, 0..3)).prop_map(|(doc, name, fields)| {
+            Item::StructDecl(StructDecl {
+                name,
+                name_span: span(),
+                fields,
+                id: id(),
+                span: span(),
+                doc,
+            })
+        });
+
+        prop_oneof![fn_decl, struct_decl].boxed()
+    }
+}
+
+impl Arbitrary for Stmt {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let var_decl = (name(), option::of(any::<Ty>()), option::of(any::<Expr>())).prop_map(
+            |(name, ty, rhs)| {
+                Stmt::VarDecl(VarDecl {
+                    name,
+                    name_span: span(),
+                    ty,
+                    rhs,
+                    span: span(),
+                })
+            },
+        );
+        let assignment = (any::<Expr>(), any::<Expr>()).prop_map(|(place, rhs)| {
+            Stmt::Assignment(Assignment {
+                place,
+                rhs,
+                span: span(),
+            })
+        });
+        let return_stmt = option::of(any::<Expr>())
+            .prop_map(|expr| Stmt::Return(ReturnStmt { expr, span: span() }));
+        let leaf = prop_oneof![
+            var_decl,
+            assignment,
+            return_stmt,
+            Just(Stmt::Break(span())),
+            Just(Stmt::Continue(span())),
+            any::<Expr>().prop_map(Stmt::Expr)
+        ];
+
+        leaf.prop_recursive(3, 16, 4, |stmt| {
+            let block = vec(stmt, 0..3);
+            let if_block = block.clone();
+            let if_stmt = (any::<Expr>(), block.clone())
+                .prop_map(|(cond, body)| IfStmt {
+                    cond,
+                    body,
+                    else_part: None,
+                    span: span(),
+                })
+                .prop_recursive(2, 4, 1, move |if_stmt| {
+                    let else_part = prop_oneof![
+                        if_block
+                            .clone()
+                            .prop_map(|body| ElsePart::Else(body, span())),
+                        if_stmt.prop_map(|if_stmt| ElsePart::ElseIf(Box::new(if_stmt))),
+                    ];
+                    (any::<Expr>(), if_block.clone(), option::of(else_part)).prop_map(
+                        |(cond, body, else_part)| IfStmt {
+                            cond,
+                            body,
+                            else_part,
+                            span: span(),
+                        },
+                    )
+                });
+            let while_stmt = (any::<Expr>(), block).prop_map(|(cond, body)| {
+                Stmt::WhileStmt(WhileStmt {
+                    cond,
+                    body,
+                    span: span(),
+                })
+            });
+            prop_oneof![if_stmt.prop_map(Stmt::IfStmt), while_stmt]
+        })
+        .boxed()
+    }
+}
+
+impl Arbitrary for Expr {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let leaf = prop_oneof![
+            any::<u64>().prop_map(|int| expr(ExprKind::Literal(Literal::Integer(int, span())))),
+            "[a-z ]{0,8}"
+                .prop_map(|string| expr(ExprKind::Literal(Literal::String(string, span())))),
+            any::<bool>().prop_map(|bool| expr(ExprKind::Literal(Literal::Bool(bool, span())))),
+            name().prop_map(|name| expr(ExprKind::Name(name))),
+        ];
+
+        leaf.prop_recursive(4, 32, 3, |inner| {
+            let bin_op_kind = prop_oneof![
+                Just(BinOpKind::Add),
+                Just(BinOpKind::Sub),
+                Just(BinOpKind::Mul),
+                Just(BinOpKind::Div),
+                Just(BinOpKind::Rem),
+                Just(BinOpKind::Eq),
+                Just(BinOpKind::Neq),
+                Just(BinOpKind::Lt),
+                Just(BinOpKind::Gt),
+                Just(BinOpKind::LtEq),
+                Just(BinOpKind::GtEq),
+                Just(BinOpKind::And),
+                Just(BinOpKind::Or),
+            ];
+            let unary_op_kind = prop_oneof![
+                Just(UnaryOpKind::Not),
+                Just(UnaryOpKind::Neg),
+                Just(UnaryOpKind::Deref),
+                Just(UnaryOpKind::AddrOf),
+            ];
+            prop_oneof![
+                (bin_op_kind, inner.clone(), inner.clone()).prop_map(|(kind, lhs, rhs)| {
+                    expr(ExprKind::BinOp(BinOp {
+                        kind,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                        span: span(),
+                    }))
+                }),
+                (unary_op_kind, inner.clone()).prop_map(|(kind, inner)| {
+                    expr(ExprKind::UnaryOp(UnaryOp {
+                        expr: Box::new(inner),
+                        kind,
+                        span: span(),
+                    }))
+                }),
+                (inner.clone(), vec(inner.clone(), 0..3)).prop_map(|(callee, args)| {
+                    expr(ExprKind::Call(Call {
+                        callee: Box::new(callee),
+                        args,
+                    }))
+                }),
+                vec(inner, 0..3).prop_map(|exprs| expr(ExprKind::Array(exprs))),
+            ]
+        })
+        .boxed()
+    }
+}
+
+impl Arbitrary for Ty {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let leaf = prop_oneof![
+            Just(Ty {
+                span: span(),
+                kind: TyKind::Bool,
+            }),
+            name().prop_map(|name| Ty {
+                span: span(),
+                kind: TyKind::Name(name),
+            }),
+        ];
+        leaf.prop_recursive(3, 3, 1, |ty| {
+            ty.prop_map(|ty| Ty {
+                span: span(),
+                kind: TyKind::Ptr(Box::new(ty)),
+            })
+        })
+        .boxed()
+    }
+}
+
+// Spans and node ids depend on the formatting, so they are reset before comparing trees.
+
+fn erase_file(file: &mut File) {
+    for item in &mut file.items {
+        erase_item(item);
+    }
+}
+
+fn erase_item(item: &mut Item) {
+    match item {
+        Item::FnDecl(fn_decl) => {
+            fn_decl.name_span = span();
+            fn_decl.id = id();
+            fn_decl.span = span();
+            fn_decl.params.iter_mut().for_each(erase_name_ty_pair);
+            fn_decl.ret_ty.iter_mut().for_each(erase_ty);
+            fn_decl.body.iter_mut().for_each(erase_stmt);
+        }
+        Item::StructDecl(struct_decl) => {
+            struct_decl.name_span = span();
+            struct_decl.id = id();
+            struct_decl.span = span();
+            struct_decl.fields.iter_mut().for_each(erase_name_ty_pair);
+        }
+    }
+}
+
+fn erase_name_ty_pair(name_ty: &mut NameTyPair) {
+    name_ty.name_span = span();
+    name_ty.id = id();
+    name_ty.span = span();
+    erase_ty(&mut name_ty.ty);
+}
+
+fn erase_ty(ty: &mut Ty) {
+    ty.span = span();
+    if let TyKind::Ptr(inner) = &mut ty.kind {
+        erase_ty(inner);
+    }
+}
+
+fn erase_stmt(stmt: &mut Stmt) {
+    match stmt {
+        Stmt::VarDecl(decl) => {
+            decl.name_span = span();
+            decl.span = span();
+            decl.ty.iter_mut().for_each(erase_ty);
+            decl.rhs.iter_mut().for_each(erase_expr);
+        }
+        Stmt::Assignment(assign) => {
+            assign.span = span();
+            erase_expr(&mut assign.place);
+            erase_expr(&mut assign.rhs);
+        }
+        Stmt::IfStmt(if_stmt) => erase_if(if_stmt),
+        Stmt::WhileStmt(while_stmt) => {
+            while_stmt.span = span();
+            erase_expr(&mut while_stmt.cond);
+            while_stmt.body.iter_mut().for_each(erase_stmt);
+        }
+        Stmt::LoopStmt(loop_stmt) => {
+            loop_stmt.span = span();
+            loop_stmt.body.iter_mut().for_each(erase_stmt);
+        }
+        Stmt::Return(return_stmt) => {
+            return_stmt.span = span();
+            return_stmt.expr.iter_mut().for_each(erase_expr);
+        }
+        Stmt::Break(break_span) | Stmt::Continue(break_span) => *break_span = span(),
+        Stmt::Item(item) => erase_item(item),
+        Stmt::Expr(expr) => erase_expr(expr),
+    }
+}
+
+fn erase_if(if_stmt: &mut IfStmt) {
+    if_stmt.span = span();
+    erase_expr(&mut if_stmt.cond);
+    if_stmt.body.iter_mut().for_each(erase_stmt);
+    match &mut if_stmt.else_part {
+        Some(ElsePart::Else(body, else_span)) => {
+            *else_span = span();
+            body.iter_mut().for_each(erase_stmt);
+        }
+        Some(ElsePart::ElseIf(if_stmt)) => erase_if(if_stmt),
+        None => {}
+    }
+}
+
+fn erase_expr(expr: &mut Expr) {
+    expr.id = id();
+    expr.span = span();
+    match &mut expr.kind {
+        ExprKind::BinOp(bin_op) => {
+            bin_op.span = span();
+            erase_expr(&mut bin_op.lhs);
+            erase_expr(&mut bin_op.rhs);
+        }
+        ExprKind::UnaryOp(unary_op) => {
+            unary_op.span = span();
+            erase_expr(&mut unary_op.expr);
+        }
+        ExprKind::FieldAccess(field_access) => erase_expr(&mut field_access.expr),
+        ExprKind::Call(call) => {
+            erase_expr(&mut call.callee);
+            call.args.iter_mut().for_each(erase_expr);
+        }
+        ExprKind::Literal(Literal::Integer(_, literal_span))
+        | ExprKind::Literal(Literal::String(_, literal_span))
+        | ExprKind::Literal(Literal::Bool(_, literal_span)) => *literal_span = span(),
+        ExprKind::Name(_) => {}
+        ExprKind::Array(exprs) => exprs.iter_mut().for_each(erase_expr),
+    }
+}
+
+proptest! {
+    #[test]
+    fn pretty_printed_files_parse_back(file: File) {
+        roundtrip(file);
+    }
+}
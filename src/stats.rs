@@ -0,0 +1,150 @@
+//! Memory usage statistics for `--print=mem-stats`. Like the timings, these are global so that
+//! they can be collected from anywhere.
+
+use std::{
+    fmt::Write,
+    mem::size_of,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::ast::{
+    visit::{self, Visitor},
+    Expr, File, Item, Stmt, Ty,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static FILES: AtomicUsize = AtomicUsize::new(0);
+static ITEMS: AtomicUsize = AtomicUsize::new(0);
+static STMTS: AtomicUsize = AtomicUsize::new(0);
+static EXPRS: AtomicUsize = AtomicUsize::new(0);
+static TYS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Adds the nodes of a parsed file to the statistics if they are enabled.
+pub fn count_ast(file: &File) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut counter = AstCounter::default();
+    visit::walk_items(&mut counter, &file.items);
+
+    FILES.fetch_add(1, Ordering::Relaxed);
+    ITEMS.fetch_add(counter.items, Ordering::Relaxed);
+    STMTS.fetch_add(counter.stmts, Ordering::Relaxed);
+    EXPRS.fetch_add(counter.exprs, Ordering::Relaxed);
+    TYS.fetch_add(counter.tys, Ordering::Relaxed);
+}
+
+pub fn report() -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "mem-stats: ast of {} files",
+        FILES.load(Ordering::Relaxed)
+    )
+    .unwrap();
+    for (name, count, size) in [
+        ("items", &ITEMS, size_of::<Item>()),
+        ("statements", &STMTS, size_of::<Stmt>()),
+        ("expressions", &EXPRS, size_of::<Expr>()),
+        ("types", &TYS, size_of::<Ty>()),
+    ] {
+        let count = count.load(Ordering::Relaxed);
+        writeln!(
+            out,
+            "mem-stats: {count:>10} {name:<12} ({size} bytes each, {} bytes total)",
+            count * size
+        )
+        .unwrap();
+    }
+
+    match allocations::peak() {
+        Some(peak) => writeln!(out, "mem-stats: {peak:>10} bytes peak heap usage").unwrap(),
+        None => writeln!(
+            out,
+            "mem-stats: peak heap usage unknown, build with `--features count-allocations`"
+        )
+        .unwrap(),
+    }
+
+    out
+}
+
+#[derive(Default)]
+struct AstCounter {
+    items: usize,
+    stmts: usize,
+    exprs: usize,
+    tys: usize,
+}
+
+impl Visitor<'_> for AstCounter {
+    fn visit_item(&mut self, item: &Item) {
+        self.items += 1;
+        visit::walk_item(self, item);
+    }
+
+    fn visit_ty(&mut self, ty: &Ty) {
+        self.tys += 1;
+        visit::walk_ty(self, ty);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        self.stmts += 1;
+        visit::walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.exprs += 1;
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(feature = "count-allocations")]
+mod allocations {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    static CURRENT: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+    struct CountingAllocator;
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+                PEAK.fetch_max(current, Ordering::Relaxed);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn peak() -> Option<usize> {
+        Some(PEAK.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(not(feature = "count-allocations"))]
+mod allocations {
+    pub fn peak() -> Option<usize> {
+        None
+    }
+}
@@ -0,0 +1,179 @@
+//! Collects how long the phases of the compiler take, for `-Ztime-passes` and `--profile`.
+//! This is a global since queries can't reach the state of the driver.
+
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<State> = Mutex::new(State {
+    start: None,
+    events: Vec::new(),
+});
+
+struct State {
+    start: Option<Instant>,
+    events: Vec<Event>,
+}
+
+struct Event {
+    name: Cow<'static, str>,
+    category: Category,
+    /// Relative to the time collection was enabled.
+    start: Duration,
+    /// `None` for events that happen at a single point in time.
+    duration: Option<Duration>,
+    thread: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Category {
+    Pass,
+    Query,
+}
+
+pub fn enable() {
+    let mut state = STATE.lock().unwrap();
+    state.start.get_or_insert_with(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Runs `f`, recording its duration under `what` if timings are enabled.
+pub fn time<T>(what: &'static str, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+
+    let start = Instant::now();
+    let result = f();
+    let duration = start.elapsed();
+
+    record(Event {
+        name: Cow::Borrowed(what),
+        category: Category::Pass,
+        start: since_start(start),
+        duration: Some(duration),
+        thread: thread_index(),
+    });
+
+    result
+}
+
+/// Records that a query started executing. The name is only computed if timings are enabled.
+pub fn query_executed(name: impl FnOnce() -> String) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    record(Event {
+        name: Cow::Owned(name()),
+        category: Category::Query,
+        start: since_start(Instant::now()),
+        duration: None,
+        thread: thread_index(),
+    });
+}
+
+fn record(event: Event) {
+    STATE.lock().unwrap().events.push(event);
+}
+
+/// A small number identifying the current thread in traces. The thread that records first is 0.
+fn thread_index() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static INDEX: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    INDEX.with(|index| *index)
+}
+
+fn since_start(instant: Instant) -> Duration {
+    let state = STATE.lock().unwrap();
+    instant.saturating_duration_since(state.start.expect("timings were not enabled"))
+}
+
+/// Renders a table with the total time spent in each phase, in the order the phases first ran.
+pub fn report() -> String {
+    let state = STATE.lock().unwrap();
+
+    let mut totals = Vec::<(&str, Duration, usize)>::new();
+    for event in &state.events {
+        let duration = match event.duration {
+            Some(duration) => duration,
+            None => continue,
+        };
+        match totals.iter_mut().find(|(name, ..)| *name == event.name) {
+            Some((_, total, count)) => {
+                *total += duration;
+                *count += 1;
+            }
+            None => totals.push((&event.name, duration, 1)),
+        }
+    }
+
+    let mut out = String::new();
+    for (what, total, count) in totals {
+        writeln!(
+            out,
+            "time: {:>10.3}ms  {what} ({count}x)",
+            total.as_secs_f64() * 1000.0
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Renders all events in the Chrome trace event format, which can be loaded into
+/// `chrome://tracing`, Perfetto or speedscope.
+pub fn chrome_trace() -> String {
+    let state = STATE.lock().unwrap();
+
+    let mut out = String::from("{\"traceEvents\":[");
+    for (i, event) in state.events.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let category = match event.category {
+            Category::Pass => "pass",
+            Category::Query => "query",
+        };
+        write!(
+            out,
+            "\n{{\"name\":\"{}\",\"cat\":\"{category}\",\"pid\":0,\"tid\":{},\"ts\":{:.3}",
+            escape_json(&event.name),
+            event.thread,
+            micros(event.start),
+        )
+        .unwrap();
+        match event.duration {
+            Some(duration) => write!(out, ",\"ph\":\"X\",\"dur\":{:.3}}}", micros(duration)),
+            None => write!(out, ",\"ph\":\"i\",\"s\":\"t\"}}"),
+        }
+        .unwrap();
+    }
+    out.push_str("\n]}\n");
+    out
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000_000.0
+}
+
+fn escape_json(str: &str) -> String {
+    let mut out = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,70 @@
+//! Errors that the grammar can't rule out but that don't need name resolution or types, like a
+//! `break` outside of a loop. Runs on files that parsed.
+
+use std::mem;
+
+use chumsky::error::Simple;
+
+use crate::{
+    ast::{
+        visit::{self, Visitor},
+        FnDecl, LoopStmt, WhileStmt,
+    },
+    parser::{parse, Error, Span},
+    Db, Diagnostics, SourceProgram,
+};
+
+#[salsa::tracked]
+pub fn validate(db: &dyn Db, source: SourceProgram) {
+    let file = match parse(db, source) {
+        Some(file) => file,
+        None => return,
+    };
+    let mut validator = Validator { db, loops: 0 };
+    visit::walk_items(&mut validator, &file.items);
+}
+
+struct Validator<'a> {
+    db: &'a dyn Db,
+    /// How many loops the current statement is in, within the current function.
+    loops: usize,
+}
+
+impl Validator<'_> {
+    fn error(&self, span: &Span, message: &str) {
+        Diagnostics::push(self.db, Error(Simple::custom(span.clone(), message)));
+    }
+}
+
+impl Visitor<'_> for Validator<'_> {
+    fn visit_fn_decl(&mut self, fn_decl: &FnDecl) {
+        // Loops around a nested function can't be left from inside of it.
+        let loops = mem::replace(&mut self.loops, 0);
+        visit::walk_fn_decl(self, fn_decl);
+        self.loops = loops;
+    }
+
+    fn visit_while_stmt(&mut self, while_stmt: &WhileStmt) {
+        self.loops += 1;
+        visit::walk_while_stmt(self, while_stmt);
+        self.loops -= 1;
+    }
+
+    fn visit_loop_stmt(&mut self, loop_stmt: &LoopStmt) {
+        self.loops += 1;
+        visit::walk_loop_stmt(self, loop_stmt);
+        self.loops -= 1;
+    }
+
+    fn visit_break(&mut self, span: &Span) {
+        if self.loops == 0 {
+            self.error(span, "`break` outside of a loop");
+        }
+    }
+
+    fn visit_continue(&mut self, span: &Span) {
+        if self.loops == 0 {
+            self.error(span, "`continue` outside of a loop");
+        }
+    }
+}
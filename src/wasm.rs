@@ -0,0 +1,30 @@
+//! Bindings for using the compiler from JavaScript, for example in a playground. Built with the
+//! `wasm` feature, e.g. `wasm-pack build --target web -- --features wasm`. There is no interpreter
+//! yet, so programs can only be checked, not run.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{highlight, resolve::resolve, Database, SourceProgram};
+
+/// Returns the errors in `source` as a JSON array of `{ message, start, end }`, where the
+/// positions are `{ line, column, offset }`. Lines and columns are zero based, and columns are
+/// in UTF-16 code units like JavaScript string indices.
+#[wasm_bindgen]
+pub fn compile(source: &str) -> String {
+    crate::diagnostics_json(source.to_string())
+}
+
+/// Returns `source` as HTML with `<span>`s around the tokens. The classes are styled by
+/// [`highlight_style`].
+#[wasm_bindgen]
+pub fn highlight(source: &str) -> String {
+    let db = Database::default();
+    let program = SourceProgram::new(&db, source.to_string(), "input.ub".into());
+    highlight::highlight(source, Some(resolve(&db, program)))
+}
+
+/// CSS for the classes used by [`highlight`].
+#[wasm_bindgen]
+pub fn highlight_style() -> String {
+    highlight::STYLE.to_string()
+}
@@ -0,0 +1,33 @@
+//! Diagnostics are colored depending on `--color` and whether stderr, which they are written to,
+//! is a terminal. Here it is a pipe.
+
+use std::process::{Command, Output};
+
+fn check(color: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ub"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env_remove("NO_COLOR")
+        .args(["check", "--color", color, "tests/ui/missing-semicolon.ub"])
+        .output()
+        .expect("could not run ub")
+}
+
+fn has_escape_codes(output: &[u8]) -> bool {
+    output.contains(&b'\x1b')
+}
+
+#[test]
+fn auto_doesnt_color_pipes() {
+    let output = check("auto");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(!output.stderr.is_empty());
+    assert!(!has_escape_codes(&output.stderr));
+}
+
+#[test]
+fn always_colors_stderr() {
+    let output = check("always");
+    assert!(output.stdout.is_empty());
+    assert!(has_escape_codes(&output.stderr));
+}
@@ -0,0 +1,42 @@
+//! `ub doc` names pages after the files they document, so two files must not end up on the same
+//! page.
+
+use std::{env, fs, path::Path, process};
+
+fn doc(dir: &Path, files: &[&str]) -> process::Output {
+    for file in files {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).expect("could not create an input directory");
+        fs::write(&path, "/// Docs.\nfn f() {}\n").expect("could not write an input file");
+    }
+    process::Command::new(env!("CARGO_BIN_EXE_ub"))
+        .current_dir(dir)
+        .args(["doc", "--out-dir", "out"])
+        .args(files)
+        .output()
+        .expect("could not run ub")
+}
+
+#[test]
+fn same_stem_in_different_directories() {
+    let dir = env::temp_dir().join(format!("ub-doc-stem-{}", process::id()));
+    let output = doc(&dir, &["a/lib.ub", "b/lib.ub"]);
+    let written = dir.join("out/lib.html").exists();
+    fs::remove_dir_all(&dir).expect("could not remove the test directory");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("would both be documented in"), "{stderr}");
+    assert!(!written);
+}
+
+#[test]
+fn file_named_index() {
+    let dir = env::temp_dir().join(format!("ub-doc-index-{}", process::id()));
+    let output = doc(&dir, &["index.ub"]);
+    fs::remove_dir_all(&dir).expect("could not remove the test directory");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("which is the index"), "{stderr}");
+}
@@ -0,0 +1,32 @@
+//! `ub emit` names output files after their inputs, so two inputs must not end up with the same
+//! output file.
+
+use std::{env, fs, process};
+
+#[test]
+fn same_stem_in_different_directories() {
+    let dir = env::temp_dir().join(format!("ub-emit-{}", process::id()));
+    let out_dir = dir.join("out");
+    for sub in ["a", "b"] {
+        fs::create_dir_all(dir.join(sub)).expect("could not create an input directory");
+        fs::write(dir.join(sub).join("main.ub"), format!("fn {sub}() {{}}\n"))
+            .expect("could not write an input file");
+    }
+    fs::create_dir_all(&out_dir).expect("could not create the output directory");
+
+    let output = process::Command::new(env!("CARGO_BIN_EXE_ub"))
+        .args(["emit", "tokens", "--out-dir"])
+        .arg(&out_dir)
+        .arg(dir.join("a/main.ub"))
+        .arg(dir.join("b/main.ub"))
+        .output()
+        .expect("could not run ub");
+    let written = fs::read_to_string(out_dir.join("main.tokens"));
+    fs::remove_dir_all(&dir).expect("could not remove the test directory");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("would both be written to"), "{stderr}");
+    // The first input keeps its output.
+    assert!(written.expect("no output file").contains("Ident(\"a\")"));
+}
@@ -0,0 +1,83 @@
+//! Compiling the same files twice, once on a single thread and once on several, has to produce
+//! byte for byte the same output and diagnostics.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+/// The files written by a run, sorted by name, then stdout and stderr.
+type Output = Vec<(PathBuf, Vec<u8>)>;
+
+fn ub_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = fs::read_dir(root.join("tests/ui"))
+        .expect("could not read tests/ui")
+        .map(|entry| entry.expect("could not read tests/ui").path())
+        .filter(|path| path.extension() == Some("ub".as_ref()))
+        .map(|path| path.strip_prefix(root).unwrap().to_owned())
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+fn run(args: &[&str], jobs: &str, out_dir: Option<&Path>) -> Output {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ub"));
+    command
+        .current_dir(root)
+        .args(["--color", "never", "-j", jobs])
+        .args(args);
+    if let Some(out_dir) = out_dir {
+        fs::create_dir_all(out_dir).expect("could not create the output directory");
+        command.arg("--out-dir").arg(out_dir);
+    }
+    let output = command
+        .args(ub_files(root))
+        .output()
+        .expect("could not run ub");
+
+    let mut files = Vec::new();
+    if let Some(out_dir) = out_dir {
+        for entry in fs::read_dir(out_dir).expect("could not read the output directory") {
+            let path = entry.expect("could not read the output directory").path();
+            let content = fs::read(&path).expect("could not read an output file");
+            files.push((path.strip_prefix(out_dir).unwrap().to_owned(), content));
+        }
+        fs::remove_dir_all(out_dir).expect("could not remove the output directory");
+    }
+    files.sort();
+    files.push(("<stdout>".into(), output.stdout));
+    files.push(("<stderr>".into(), output.stderr));
+    files
+}
+
+fn assert_reproducible(args: &[&str], writes_files: bool) {
+    let out_dir = |run: &str| {
+        let name = format!("ub-reproducible-{}-{}-{run}", process::id(), args.join("-"));
+        writes_files.then(|| env::temp_dir().join(name))
+    };
+    let first = run(args, "1", out_dir("first").as_deref());
+    let second = run(args, "4", out_dir("second").as_deref());
+    assert_eq!(first, second);
+}
+
+#[test]
+fn check() {
+    assert_reproducible(&["check"], false);
+}
+
+#[test]
+fn emit_tokens() {
+    assert_reproducible(&["emit", "tokens"], true);
+}
+
+#[test]
+fn emit_ast() {
+    assert_reproducible(&["emit", "ast"], true);
+}
+
+#[test]
+fn doc() {
+    assert_reproducible(&["doc"], true);
+}
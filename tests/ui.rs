@@ -0,0 +1,146 @@
+//! Checks the files in `tests/ui` with the `ub` binary. Every error has to be annotated with
+//! `//~ ERROR <part of the message>` on the line it is reported on, or with `//~^ ERROR` on a
+//! later line, with one `^` for each line above. The rendered diagnostics on stderr are compared
+//! with the `.stderr` file next to the test, which `cargo test --test ui -- --bless` updates.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, Output},
+};
+
+struct Annotation {
+    /// One based, like the line numbers in rendered diagnostics.
+    line: u32,
+    message: String,
+}
+
+fn main() -> ExitCode {
+    let bless = env::args().any(|arg| arg == "--bless");
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    let mut tests = fs::read_dir(root.join("tests/ui"))
+        .expect("could not read tests/ui")
+        .map(|entry| entry.expect("could not read tests/ui").path())
+        .filter(|path| path.extension() == Some("ub".as_ref()))
+        .map(|path| path.strip_prefix(root).unwrap().to_owned())
+        .collect::<Vec<_>>();
+    tests.sort();
+
+    let mut failed = 0;
+    for test in &tests {
+        let problems = run_test(root, test, bless);
+        if problems.is_empty() {
+            println!("test {} ... ok", test.display());
+        } else {
+            println!("test {} ... FAILED", test.display());
+            for problem in problems {
+                println!("    {problem}");
+            }
+            failed += 1;
+        }
+    }
+
+    let status = if failed == 0 { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {status}. {} passed; {failed} failed",
+        tests.len() - failed
+    );
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Returns what went wrong.
+fn run_test(root: &Path, test: &Path, bless: bool) -> Vec<String> {
+    let source = fs::read_to_string(root.join(test)).expect("could not read test");
+    let mut problems = Vec::new();
+
+    let output = check(root, test, "json");
+    let mut annotations = annotations(&source);
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        if !line.starts_with('{') {
+            continue;
+        }
+        let diagnostic: serde_json::Value =
+            serde_json::from_str(line).expect("invalid JSON diagnostic");
+        let message = diagnostic["message"].as_str().unwrap_or_default();
+        let line = diagnostic["start"]["line"].as_u64().unwrap_or_default() as u32 + 1;
+        match annotations
+            .iter()
+            .position(|annotation| annotation.line == line && message.contains(&annotation.message))
+        {
+            Some(i) => drop(annotations.remove(i)),
+            None => problems.push(format!("unexpected error on line {line}: {message}")),
+        }
+    }
+    for annotation in annotations {
+        problems.push(format!(
+            "expected error on line {} was not reported: {}",
+            annotation.line, annotation.message
+        ));
+    }
+
+    let output = check(root, test, "human");
+    if !output.stdout.is_empty() {
+        problems.push(format!(
+            "`ub check` printed to stdout:\n{}",
+            String::from_utf8_lossy(&output.stdout)
+        ));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    let stderr_path = root.join(test).with_extension("stderr");
+    if bless {
+        bless_stderr(&stderr_path, &stderr);
+    } else {
+        let expected = fs::read_to_string(&stderr_path).unwrap_or_default();
+        if stderr != expected {
+            problems.push(format!(
+                "stderr differs from {}, rerun with `-- --bless` to update it:\n{stderr}",
+                stderr_path.display()
+            ));
+        }
+    }
+    problems
+}
+
+fn check(root: &Path, test: &Path, error_format: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ub"))
+        .current_dir(root)
+        .args(["check", "--color", "never", "--error-format", error_format])
+        .arg(test)
+        .output()
+        .expect("could not run ub")
+}
+
+fn annotations(source: &str) -> Vec<Annotation> {
+    let mut annotations = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let annotation = match line.split_once("//~") {
+            Some((_, annotation)) => annotation,
+            None => continue,
+        };
+        let message = annotation.trim_start_matches('^');
+        let lines_above = annotation.len() - message.len();
+        let message = message.trim_start();
+        let message = message.strip_prefix("ERROR").unwrap_or(message).trim();
+        annotations.push(Annotation {
+            line: (i + 1 - lines_above) as u32,
+            message: message.to_string(),
+        });
+    }
+    annotations
+}
+
+/// Tests without errors don't have a stderr file.
+fn bless_stderr(path: &PathBuf, stderr: &str) {
+    if stderr.is_empty() {
+        if path.exists() {
+            fs::remove_file(path).expect("could not remove stderr file");
+        }
+    } else {
+        fs::write(path, stderr).expect("could not write stderr file");
+    }
+}